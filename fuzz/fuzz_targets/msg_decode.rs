@@ -0,0 +1,12 @@
+#![no_main]
+
+use barrel::Msg;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut rest = data;
+    while let Ok(Some((_msg, len))) = Msg::decode(rest) {
+        assert!(len > 0 && len <= rest.len());
+        rest = &rest[len..];
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use barrel::Response;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    for line in data.split(|&b| b == b'\n') {
+        let _ = Response::decode(line);
+    }
+});
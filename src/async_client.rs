@@ -0,0 +1,116 @@
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use crate::{Encoder, Encoding, Error, Msg, Response, Size};
+
+/// Async counterpart of [`Client`](crate::Client) based on tokio. Needs **features = ["async"]**.
+pub struct AsyncClient {
+    write: BufWriter<OwnedWriteHalf>,
+    read: BufReader<OwnedReadHalf>,
+    encoder: Encoder,
+    /// Reused buffer for encoding a single message.
+    msg_buf: Vec<u8>,
+    /// Reused buffer for a single response line.
+    line_buf: Vec<u8>,
+}
+
+impl AsyncClient {
+    pub async fn connect(addr: impl ToSocketAddrs) -> Result<Self, Error> {
+        let stream = TcpStream::connect(addr).await.map_err(Error::Connect)?;
+        let (read, write) = stream.into_split();
+        let write = BufWriter::new(write);
+        let read = BufReader::new(read);
+        Ok(Self { write, read, encoder: Encoder::default(), msg_buf: vec![], line_buf: vec![] })
+    }
+
+    /// See [`Client::set_encoding`](crate::Client::set_encoding).
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoder.set_encoding(encoding);
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoder.encoding()
+    }
+
+    /// See [`Client::set_tile_offsets`](crate::Client::set_tile_offsets).
+    pub fn set_tile_offsets(&mut self, tile_size: Option<u32>) {
+        self.encoder.set_tile_offsets(tile_size);
+    }
+
+    pub async fn get_size(&mut self) -> Result<Size, Error> {
+        let resp = self.send_recv(Msg::GetSize).await?;
+        match resp {
+            Response::Size(size) => Ok(size),
+            _ => Err(Error::WrongResponse),
+        }
+    }
+
+    /// Flushes the internal buffer after sending.
+    pub async fn send(&mut self, msg: Msg) -> Result<(), Error> {
+        self.send_buffered(msg).await?;
+        self.flush().await?;
+        Ok(())
+    }
+
+    /// Send and receive a response. For message which don't
+    /// [`Msg::expect_response`], an error is returned.
+    pub async fn send_recv(&mut self, msg: Msg) -> Result<Response, Error> {
+        if !msg.expect_response() {
+            return Err(Error::NoResponseExpected);
+        }
+        self.send(msg).await?;
+        let resp = self.recv(msg).await?;
+        Ok(resp)
+    }
+
+    /// Flushes after sending all messages.
+    pub async fn send_all(&mut self, msgs: &[Msg]) -> Result<Vec<Response>, Error> {
+        for msg in msgs {
+            self.send_buffered(*msg).await?;
+        }
+        self.flush().await?;
+        let mut responses = vec![];
+        for msg in msgs.iter().filter(|msg| msg.expect_response()) {
+            responses.push(self.recv(*msg).await?);
+        }
+        Ok(responses)
+    }
+
+    /// Does not explicitly flush the buffer after sending.
+    /// Use [`AsyncClient::send`] or manual [`AsyncClient::flush`].
+    #[inline]
+    pub async fn send_buffered(&mut self, msg: Msg) -> Result<(), Error> {
+        self.msg_buf.clear();
+        self.encoder.encode(&msg, &mut self.msg_buf).map_err(Error::SendCmd)?;
+        self.write.write_all(&self.msg_buf).await.map_err(Error::SendCmd)?;
+        Ok(())
+    }
+
+    /// Receives the response to `request`.
+    async fn recv(&mut self, request: Msg) -> Result<Response, Error> {
+        if request != Msg::Help {
+            return Ok(Response::decode(self.recv_line().await?)?);
+        }
+        let mut help = String::new();
+        while let Some(line) = Response::decode_help_line(self.recv_line().await?) {
+            if !help.is_empty() {
+                help.push('\n');
+            }
+            help.push_str(&line);
+        }
+        Ok(Response::Help(help))
+    }
+
+    #[inline]
+    async fn recv_line(&mut self) -> Result<&[u8], Error> {
+        self.line_buf.clear();
+        if self.read.read_until(b'\n', &mut self.line_buf).await.map_err(Error::Receive)? == 0 {
+            return Err(Error::MissingData);
+        }
+        Ok(self.line_buf.strip_suffix(b"\n").unwrap_or(&self.line_buf))
+    }
+
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        self.write.flush().await.map_err(Error::SendCmd)
+    }
+}
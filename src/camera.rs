@@ -1,85 +1,311 @@
 use std::io;
-use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use thiserror::Error;
 use v4l::buffer::Type;
 use v4l::{Device, FourCC};
-use v4l::format::Colorspace;
 use v4l::io::traits::{CaptureStream, Stream};
 use v4l::prelude::UserptrStream;
+use v4l::video::capture::Parameters;
 use v4l::video::Capture;
+use zune_jpeg::errors::DecodeErrors;
 use zune_jpeg::zune_core::colorspace::ColorSpace;
 use zune_jpeg::zune_core::options::DecoderOptions;
 use zune_jpeg::JpegDecoder;
 
-use crate::{Msg, Pos, Rgba};
-
-// TODO maybe use v4l directly as nokhwa seems to not build
+use crate::Size;
+use crate::filter::{self, PixelFilter};
+use crate::source::{FrameSource, SourceFrame};
 
+/// Captures a V4L camera as a [`FrameSource`] in true color, unless a
+/// [`PixelFilter`] is set. Needs **features = ["camera"]**.
 pub struct CameraWriter {
-    dev: Device,
     stream: UserptrStream,
-    dim: (u32, u32),
-    current_mod: u8
+    format: PixelFormat,
+    size: Size,
+    /// Bytes per row of raw frames.
+    stride: usize,
+    fps: Option<f64>,
+    pixels: Vec<u8>,
+    filter: Option<Box<dyn PixelFilter>>,
+}
+
+/// Options for opening a camera via [`CameraWriter::open`]. Cameras may not support
+/// the requested format, the [`CameraWriter`] reports the one they chose instead.
+#[derive(Clone, Debug)]
+pub struct CameraOptions {
+    device: CameraDevice,
+    size: Option<Size>,
+    fps: Option<u32>,
+    format: PixelFormat,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CameraDevice {
+    /// Index of the camera, as in `/dev/video<index>`.
+    Index(usize),
+    Path(PathBuf),
 }
 
+/// Pixel formats a [`CameraWriter`] can capture.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum PixelFormat {
+    /// Motion JPEG, supported by most USB cameras at high resolutions.
+    #[default]
+    Mjpg,
+    /// Packed YUV 4:2:2.
+    Yuyv,
+    /// Packed 24 bit RGB.
+    Rgb,
+}
+
+/// Errors of a [`CameraWriter`]. After a failed frame, the next frame can be
+/// requested or the camera replaced by another [`CameraWriter`].
 #[derive(Error, Debug)]
 pub enum Error {
-    #[error("Unable to send camera frame")]
-    SendCameraFrame(#[from] io::Error),
-    // #[error("Unable to get camera frame")]
-    // GetCameraFrame(#[source] NokhwaError),
-    // #[error("Unable to set up camera")]
-    // CameraSetup(#[source] NokhwaError)
+    #[error("Unable to open camera")]
+    Open(#[source] io::Error),
+    #[error("Unable to set camera format")]
+    Format(#[source] io::Error),
+    #[error("Camera chose the unsupported pixel format {0}")]
+    UnsupportedFormat(String),
+    #[error("Unable to start camera stream")]
+    Stream(#[source] io::Error),
+    #[error("Unable to get camera frame")]
+    Frame(#[source] io::Error),
+    #[error("Camera frame has {actual} bytes, expected at least {expected}")]
+    ShortFrame { expected: usize, actual: usize },
+    #[error("Unable to decode camera frame")]
+    Decode(#[source] DecodeErrors),
+}
+
+impl CameraOptions {
+    /// Defaults to `/dev/video0`.
+    pub fn device(mut self, device: impl Into<CameraDevice>) -> Self {
+        self.device = device.into();
+        self
+    }
+
+    /// Requested resolution. Defaults to 1280x720.
+    pub fn size(mut self, size: Size) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Requested frame rate. Defaults to the frame rate the camera is set to.
+    pub fn fps(mut self, fps: u32) -> Self {
+        self.fps = Some(fps);
+        self
+    }
+
+    /// Requested pixel format. Defaults to [`PixelFormat::Mjpg`].
+    pub fn format(mut self, format: PixelFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+impl Default for CameraOptions {
+    fn default() -> Self {
+        Self {
+            device: CameraDevice::Index(0),
+            size: Some(Size::new(1280, 720)),
+            fps: None,
+            format: PixelFormat::default(),
+        }
+    }
+}
+
+impl From<usize> for CameraDevice {
+    fn from(index: usize) -> Self {
+        Self::Index(index)
+    }
+}
+
+impl From<PathBuf> for CameraDevice {
+    fn from(path: PathBuf) -> Self {
+        Self::Path(path)
+    }
+}
+
+impl From<&Path> for CameraDevice {
+    fn from(path: &Path) -> Self {
+        Self::Path(path.to_path_buf())
+    }
+}
+
+impl PixelFormat {
+    fn fourcc(self) -> FourCC {
+        match self {
+            PixelFormat::Mjpg => FourCC::new(b"MJPG"),
+            PixelFormat::Yuyv => FourCC::new(b"YUYV"),
+            PixelFormat::Rgb => FourCC::new(b"RGB3"),
+        }
+    }
+
+    fn from_fourcc(fourcc: FourCC) -> Option<Self> {
+        [PixelFormat::Mjpg, PixelFormat::Yuyv, PixelFormat::Rgb].into_iter().find(|format| format.fourcc() == fourcc)
+    }
+
+    /// Bytes per pixel of raw formats.
+    fn bytes_per_px(self) -> usize {
+        match self {
+            PixelFormat::Mjpg => 0,
+            PixelFormat::Yuyv => 2,
+            PixelFormat::Rgb => 3,
+        }
+    }
 }
 
 impl CameraWriter {
+    /// Opens camera `/dev/video<camera_id>` with the default [`CameraOptions`].
     pub fn new(camera_id: usize) -> Result<Self, Error> {
-        let dev = Device::new(camera_id).unwrap();
-        let mut fmt = dev.format().unwrap();
-        fmt.width = 1280;
-        fmt.height = 720;
-        fmt.fourcc = FourCC::new(b"MJPG");
-        dev.set_format(&fmt).unwrap();
-        let mut stream = UserptrStream::new(&dev, Type::VideoCapture).unwrap();
-        stream.start().unwrap();
-        // camera.set_frame_format(FrameFormat::RAWRGB).map_err(Error::CameraSetup)?;
-        // camera.open_stream().map_err(Error::CameraSetup)?;
-        Ok(Self { dev, stream, dim: (fmt.width, fmt.height) , current_mod: 1})
-    }
-
-    pub fn capture<W: Write>(&mut self, buf: &mut W) -> Result<(), Error> {
-        let (frame, _meta) = self.stream.next().unwrap();
-        let mut options = DecoderOptions::default().jpeg_set_out_colorspace(ColorSpace::RGBA);
-        let mut decoder = JpegDecoder::new_with_options(frame,options);
-        let pixels = decoder.decode().unwrap();
-        // four three for rgb pixel size
-        for (y, row ) in  pixels.chunks_exact(self.dim.0 as usize * 4).enumerate() {
-            for (x, px) in row.chunks_exact(4).enumerate() {
-                let pos = Pos::new(x as u32, y as u32);
-
-                let &[r, g, b, a] = px else {
-                    unreachable!("chunk_exact by 4");
-                };
-                // let mut rgb = [r, g, b];
-                // rgb.rotate_left((self.current_mod % 3) as usize);
-                // let [r, g, b] = rgb;
-                let [r,g,b] = match self.current_mod % 3 {
-                    0 => [r, 0, 0],
-                    1 => [0, g, 0],
-                    2 => [0, 0, b],
-                    _ => unreachable!()
-                };
-                // let col = Rgba::new(g/ self.current_mod * self.current_mod, b/ self.current_mod * self.current_mod, r / self.current_mod * self.current_mod, Some(a));
-                let col = Rgba::new(r, g, b , Some(a));
-                Msg::SetPx(pos, col).encode(buf)?;
+        Self::open(&CameraOptions::default().device(camera_id))
+    }
+
+    pub fn open(options: &CameraOptions) -> Result<Self, Error> {
+        let dev = match &options.device {
+            CameraDevice::Index(index) => Device::new(*index),
+            CameraDevice::Path(path) => Device::with_path(path),
+        }.map_err(Error::Open)?;
+        let mut fmt = dev.format().map_err(Error::Format)?;
+        if let Some(size) = options.size {
+            fmt.width = size.x;
+            fmt.height = size.y;
+        }
+        fmt.fourcc = options.format.fourcc();
+        // Drivers adjust unsupported requests to the closest format they support
+        let fmt = dev.set_format(&fmt).map_err(Error::Format)?;
+        let format = PixelFormat::from_fourcc(fmt.fourcc)
+            .ok_or_else(|| Error::UnsupportedFormat(fmt.fourcc.to_string()))?;
+        if let Some(fps) = options.fps {
+            dev.set_params(&Parameters::with_fps(fps)).map_err(Error::Format)?;
+        }
+        // Not all drivers report their frame rate
+        let fps = dev.params().ok().and_then(|params| {
+            let interval = params.interval;
+            (interval.numerator != 0).then(|| interval.denominator as f64 / interval.numerator as f64)
+        });
+        let mut stream = UserptrStream::new(&dev, Type::VideoCapture).map_err(Error::Stream)?;
+        stream.start().map_err(Error::Stream)?;
+        Ok(Self {
+            stream,
+            format,
+            size: Size::new(fmt.width, fmt.height),
+            stride: fmt.stride as usize,
+            fps,
+            pixels: vec![],
+            filter: None,
+        })
+    }
+
+    /// Negotiated pixel format.
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    /// Negotiated resolution. JPEG frames may still have another size.
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    /// Negotiated frame rate, `None` if the camera doesn't report it.
+    pub fn fps(&self) -> Option<f64> {
+        self.fps
+    }
+
+    /// Filter applied to the pixels of every captured frame.
+    pub fn set_filter(&mut self, filter: Option<Box<dyn PixelFilter>>) {
+        self.filter = filter;
+    }
+}
+
+impl FrameSource for CameraWriter {
+    /// Captures the next frame, never returns `None`.
+    fn next_frame(&mut self) -> Result<Option<SourceFrame<'_>>, crate::Error> {
+        let (frame, _meta) = self.stream.next().map_err(Error::Frame)?;
+        let (width, height) = match self.format {
+            PixelFormat::Mjpg => {
+                let options = DecoderOptions::default().jpeg_set_out_colorspace(ColorSpace::RGBA);
+                let mut decoder = JpegDecoder::new_with_options(frame, options);
+                self.pixels = decoder.decode().map_err(Error::Decode)?;
+                let (width, height) = decoder.dimensions().expect("Headers were decoded");
+                (width as u32, height as u32)
+            }
+            format => {
+                decode_raw(frame, format, self.size, self.stride, &mut self.pixels)?;
+                (self.size.x, self.size.y)
             }
+        };
+        if let Some(filter) = &mut self.filter {
+            filter::apply(filter.as_mut(), &mut self.pixels);
         }
+        Ok(Some(SourceFrame::rgba(width, height, &self.pixels, Duration::ZERO)))
+    }
+}
 
-        self.current_mod = self.current_mod.wrapping_add(1);
-        if self.current_mod == 0 {
-            self.current_mod += 1;
+/// Converts a frame in a raw `format` with rows of `stride` bytes to RGBA pixels.
+fn decode_raw(frame: &[u8], format: PixelFormat, size: Size, stride: usize, pixels: &mut Vec<u8>) -> Result<(), Error> {
+    let row_len = size.x as usize * format.bytes_per_px();
+    let stride = stride.max(row_len);
+    let expected = (stride * size.y as usize).saturating_sub(stride - row_len);
+    if frame.len() < expected {
+        return Err(Error::ShortFrame { expected, actual: frame.len() });
+    }
+    pixels.clear();
+    for row in frame.chunks(stride).take(size.y as usize) {
+        let row = &row[..row_len];
+        match format {
+            PixelFormat::Mjpg => unreachable!("MJPG isn't a raw format"),
+            PixelFormat::Yuyv => pixels.extend(row.chunks_exact(4).flat_map(|px| {
+                let [r0, g0, b0] = yuv_to_rgb(px[0], px[1], px[3]);
+                let [r1, g1, b1] = yuv_to_rgb(px[2], px[1], px[3]);
+                [r0, g0, b0, u8::MAX, r1, g1, b1, u8::MAX]
+            })),
+            PixelFormat::Rgb => pixels.extend(row.chunks_exact(3).flat_map(|px| [px[0], px[1], px[2], u8::MAX])),
         }
+    }
+    Ok(())
+}
 
-        Ok(())
+/// BT.601 conversion of limited range YUV, as sent by most cameras.
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let c = 298 * (y as i32 - 16);
+    let d = u as i32 - 128;
+    let e = v as i32 - 128;
+    let clamp = |val: i32| ((val + 128) >> 8).clamp(0, 255) as u8;
+    [clamp(c + 409 * e), clamp(c - 100 * d - 208 * e), clamp(c + 516 * d)]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Size;
+    use crate::camera::{decode_raw, yuv_to_rgb, Error, PixelFormat};
+
+    #[test]
+    fn yuv_colors() {
+        assert_eq!(yuv_to_rgb(235, 128, 128), [255, 255, 255]);
+        assert_eq!(yuv_to_rgb(16, 128, 128), [0, 0, 0]);
+        assert_eq!(yuv_to_rgb(81, 90, 240), [255, 0, 0]);
+    }
+
+    #[test]
+    fn decode_yuyv() {
+        let mut pixels = vec![];
+        // Y0 U Y1 V, the pixels share the chroma
+        decode_raw(&[235, 128, 16, 128, 81, 90, 81, 240], PixelFormat::Yuyv, Size::new(4, 1), 8, &mut pixels).unwrap();
+        assert_eq!(pixels, [255, 255, 255, 255, 0, 0, 0, 255, 255, 0, 0, 255, 255, 0, 0, 255]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn decode_rgb_with_stride() {
+        // Rows are padded to 8 bytes, except for the last one
+        let frame = [1, 2, 3, 4, 5, 6, 0, 0, 7, 8, 9, 10, 11, 12];
+        let mut pixels = vec![];
+        decode_raw(&frame, PixelFormat::Rgb, Size::new(2, 2), 8, &mut pixels).unwrap();
+        assert_eq!(pixels, [1, 2, 3, 255, 4, 5, 6, 255, 7, 8, 9, 255, 10, 11, 12, 255]);
+
+        let err = decode_raw(&frame[..13], PixelFormat::Rgb, Size::new(2, 2), 8, &mut pixels).unwrap_err();
+        assert!(matches!(err, Error::ShortFrame { expected: 14, actual: 13 }));
+    }
+}
@@ -0,0 +1,148 @@
+use crate::{Client, Error, Msg, Pos, Rect, Rgba, Size};
+
+/// In-memory model of what the board should look like. Tracks which pixels changed
+/// since the last [`Canvas::flush`], so only those are sent.
+#[derive(Debug, Clone)]
+pub struct Canvas {
+    size: Size,
+    pixels: Vec<Rgba>,
+    /// Whether the pixel at an index is contained in `dirty_idx`.
+    dirty: Vec<bool>,
+    dirty_idx: Vec<usize>,
+}
+
+impl Canvas {
+    /// A canvas filled with opaque black. Nothing is dirty initially.
+    pub fn new(size: Size) -> Self {
+        let len = size.x as usize * size.y as usize;
+        Self {
+            size,
+            pixels: vec![Rgba::new(0, 0, 0, None); len],
+            dirty: vec![false; len],
+            dirty_idx: vec![],
+        }
+    }
+
+    pub fn size(&self) -> Size {
+        self.size
+    }
+
+    /// `None` if `pos` is outside the canvas.
+    pub fn get(&self, pos: Pos) -> Option<Rgba> {
+        self.index(pos).map(|idx| self.pixels[idx])
+    }
+
+    /// Sets the pixel and marks it dirty if its color changed. Positions outside the
+    /// canvas are ignored.
+    pub fn set(&mut self, pos: Pos, col: Rgba) {
+        let Some(idx) = self.index(pos) else {
+            return;
+        };
+        if self.pixels[idx] == col {
+            return;
+        }
+        self.pixels[idx] = col;
+        self.mark_dirty(idx);
+    }
+
+    pub fn fill(&mut self, col: Rgba) {
+        self.fill_rect(Rect::new(Pos::default(), self.size), col);
+    }
+
+    /// Fills the part of `rect` which lies inside the canvas.
+    pub fn fill_rect(&mut self, rect: Rect, col: Rgba) {
+        let x_end = rect.pos.x.saturating_add(rect.size.x).min(self.size.x);
+        let y_end = rect.pos.y.saturating_add(rect.size.y).min(self.size.y);
+        for y in rect.pos.y..y_end {
+            for x in rect.pos.x..x_end {
+                self.set(Pos::new(x, y), col);
+            }
+        }
+    }
+
+    /// Number of pixels which changed since the last flush.
+    pub fn dirty_count(&self) -> usize {
+        self.dirty_idx.len()
+    }
+
+    /// Mark every pixel dirty, e.g. to repaint the whole canvas.
+    pub fn mark_all_dirty(&mut self) {
+        for idx in 0..self.pixels.len() {
+            self.mark_dirty(idx);
+        }
+    }
+
+    /// Returns [`Msg::SetPx`] commands for all dirty pixels in row-major order and
+    /// clears the dirty state.
+    pub fn take_dirty(&mut self) -> Vec<Msg> {
+        let msgs = self.dirty_msgs().collect();
+        self.clear_dirty();
+        msgs
+    }
+
+    /// Sends all dirty pixels in row-major order and flushes the client. The dirty
+    /// state is only cleared if sending succeeded.
+    pub fn flush(&mut self, client: &mut Client) -> Result<(), Error> {
+        for msg in self.dirty_msgs() {
+            client.send_buffered(msg)?;
+        }
+        client.flush()?;
+        self.clear_dirty();
+        Ok(())
+    }
+
+    fn dirty_msgs(&mut self) -> impl Iterator<Item = Msg> + '_ {
+        self.dirty_idx.sort_unstable();
+        let width = self.size.x as usize;
+        let pixels = &self.pixels;
+        self.dirty_idx.iter().map(move |&idx| {
+            let pos = Pos::new((idx % width) as u32, (idx / width) as u32);
+            Msg::SetPx(pos, pixels[idx])
+        })
+    }
+
+    fn clear_dirty(&mut self) {
+        for idx in self.dirty_idx.drain(..) {
+            self.dirty[idx] = false;
+        }
+    }
+
+    fn mark_dirty(&mut self, idx: usize) {
+        if !self.dirty[idx] {
+            self.dirty[idx] = true;
+            self.dirty_idx.push(idx);
+        }
+    }
+
+    fn index(&self, pos: Pos) -> Option<usize> {
+        (pos.x < self.size.x && pos.y < self.size.y)
+            .then(|| pos.y as usize * self.size.x as usize + pos.x as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Msg, Pos, Rect, Rgba, Size};
+    use crate::canvas::Canvas;
+
+    #[test]
+    fn tracks_changed_pixels() {
+        let mut canvas = Canvas::new(Size::new(4, 4));
+        canvas.set(Pos::new(3, 1), Rgba::red());
+        canvas.set(Pos::new(0, 0), Rgba::new(0, 0, 0, None));
+        canvas.fill_rect(Rect::new(Pos::new(1, 0), Size::new(1, 10)), Rgba::blue());
+        canvas.set(Pos::new(1, 1), Rgba::blue());
+        canvas.set(Pos::new(9, 9), Rgba::red());
+        assert_eq!(canvas.get(Pos::new(3, 1)), Some(Rgba::red()));
+        assert_eq!(canvas.get(Pos::new(4, 1)), None);
+        assert_eq!(canvas.dirty_count(), 5);
+
+        let msgs = canvas.take_dirty();
+        let positions: Vec<_> = msgs.iter().map(|msg| match msg {
+            Msg::SetPx(pos, _) => (pos.x, pos.y),
+            _ => unreachable!(),
+        }).collect();
+        assert_eq!(positions, [(1, 0), (1, 1), (3, 1), (1, 2), (1, 3)]);
+        assert_eq!(canvas.dirty_count(), 0);
+    }
+}
@@ -1,6 +1,7 @@
 use std::io;
-use std::io::Write;
-use crate::{Error, Msg, Pos, Response, Rgba, Size};
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+use crate::{Encoding, Error, Msg, ParseError, ParseErrorKind, Pos, Response, Rgba, Size};
 
 impl Msg {
     pub(crate) fn encode<W: Write>(&self, buf: &mut W) -> Result<(), io::Error> {
@@ -15,6 +16,10 @@ impl Msg {
                 buf.write_all(b"PX ")?;
                 pos.encode(buf)?;
             }
+            Msg::Offset(pos) => {
+                buf.write_all(b"OFFSET ")?;
+                pos.encode(buf)?;
+            }
             Msg::GetSize => {
                 buf.write_all(b"SIZE")?;
             }
@@ -26,36 +31,332 @@ impl Msg {
         Ok(())
     }
 
+    pub(crate) fn encode_as<W: Write>(&self, encoding: Encoding, buf: &mut W) -> Result<(), io::Error> {
+        match (encoding, self) {
+            (Encoding::Binary, Msg::SetPx(pos, rgba)) => {
+                match (u16::try_from(pos.x), u16::try_from(pos.y)) {
+                    (Ok(x), Ok(y)) => {
+                        let mut cmd = [0; 10];
+                        cmd[..2].copy_from_slice(b"PB");
+                        cmd[2..4].copy_from_slice(&x.to_le_bytes());
+                        cmd[4..6].copy_from_slice(&y.to_le_bytes());
+                        cmd[6..].copy_from_slice(&rgba.to_bytes());
+                        buf.write_all(&cmd)
+                    }
+                    _ => self.encode(buf)
+                }
+            }
+            _ => self.encode(buf)
+        }
+    }
+
+    /// Decodes the first command of `buf` in either [`Encoding`], e.g. on the server side.
+    /// Returns the message and the number of bytes it took up, or `None` if `buf` doesn't
+    /// contain a complete command yet. Binary commands decode fully opaque colors without
+    /// alpha value.
+    pub fn decode(buf: &[u8]) -> Result<Option<(Self, usize)>, ParseError> {
+        if let Some(cmd) = buf.strip_prefix(b"PB") {
+            let Some(cmd) = cmd.get(..8) else {
+                return Ok(None);
+            };
+            let x = u16::from_le_bytes([cmd[0], cmd[1]]);
+            let y = u16::from_le_bytes([cmd[2], cmd[3]]);
+            let a = (cmd[7] != u8::MAX).then_some(cmd[7]);
+            let msg = Msg::SetPx(Pos::new(x as u32, y as u32), Rgba::new(cmd[4], cmd[5], cmd[6], a));
+            return Ok(Some((msg, 10)));
+        }
+        let Some(end) = buf.iter().position(|&b| b == b'\n') else {
+            return Ok(None);
+        };
+        let line = &buf[..end];
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let msg = decode_cmd(line)
+            .map_err(|kind| ParseError { kind, line: String::from_utf8_lossy(line).into_owned() })?;
+        Ok(Some((msg, end + 1)))
+    }
+
     pub(crate) fn expect_response(&self) -> bool {
         match self {
-            Msg::SetPx(_, _) => false,
+            Msg::SetPx(_, _) | Msg::Offset(_) => false,
             Msg::GetPx(_) | Msg::GetSize | Msg::Help => true,
         }
     }
 }
 
+/// Encodes [`Msg`]s with the configured [`Encoding`] and keeps track of the
+/// [`Msg::Offset`] which is active on the server.
+#[derive(Debug, Clone)]
+pub struct Encoder {
+    encoding: Encoding,
+    tile_size: Option<u32>,
+    /// `None` if the offset on the server is unknown.
+    offset: Option<Pos>,
+    /// Whether `offset` was set by the encoder itself and not by an explicit
+    /// [`Msg::Offset`].
+    auto_offset: bool,
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Self::new(Encoding::default())
+    }
+}
+
+impl Encoder {
+    pub fn new(encoding: Encoding) -> Self {
+        Self {
+            encoding,
+            tile_size: None,
+            offset: Some(Pos::default()),
+            auto_offset: false,
+        }
+    }
+
+    /// A fresh encoder with the same [`Encoding`] and tile size as `self`.
+    pub fn with_same_format(&self) -> Self {
+        let mut encoder = Self::new(self.encoding);
+        encoder.set_tile_offsets(self.tile_size);
+        encoder
+    }
+
+    /// Whether `self` and `other` produce the same commands for the same messages.
+    pub fn same_format(&self, other: &Encoder) -> bool {
+        self.encoding == other.encoding && self.tile_size == other.tile_size
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+
+    pub fn tile_offsets(&self) -> Option<u32> {
+        self.tile_size
+    }
+
+    /// With a `tile_size`, coordinates of [`Msg::SetPx`] are treated as absolute and
+    /// sent relative to the origin of the tile they lie in, emitting a [`Msg::Offset`]
+    /// whenever the tile changes. This shortens [`Encoding::Text`] commands for
+    /// images far from the origin, especially when pixels are sent tile by tile.
+    ///
+    /// `None` or a size of `0` disables automatic offsets. Explicit [`Msg::Offset`]
+    /// commands are always sent as is.
+    pub fn set_tile_offsets(&mut self, tile_size: Option<u32>) {
+        self.tile_size = tile_size.filter(|&size| size > 0);
+    }
+
+    /// Encodes `msg` into `buf`. [`Msg::Help`] is followed by a `SIZE` request, whose
+    /// response marks the end of the free-form help text.
+    pub fn encode<W: Write>(&mut self, msg: &Msg, buf: &mut W) -> Result<(), io::Error> {
+        match *msg {
+            Msg::SetPx(pos, col) => {
+                if let Some(tile_size) = self.tile_size {
+                    let origin = Pos::new(pos.x / tile_size * tile_size, pos.y / tile_size * tile_size);
+                    self.auto_offset(origin, buf)?;
+                    let rel = Pos::new(pos.x - origin.x, pos.y - origin.y);
+                    return Msg::SetPx(rel, col).encode_as(self.encoding, buf);
+                }
+                self.reset_auto_offset(buf)?;
+            }
+            Msg::GetPx(_) => self.reset_auto_offset(buf)?,
+            Msg::Offset(pos) => {
+                self.offset = Some(pos);
+                self.auto_offset = false;
+            }
+            Msg::GetSize => {}
+            Msg::Help => {
+                msg.encode_as(self.encoding, buf)?;
+                return Msg::GetSize.encode_as(self.encoding, buf);
+            }
+        }
+        msg.encode_as(self.encoding, buf)
+    }
+
+    /// Start a segment of commands which can be sent independently of previous
+    /// segments encoded with the same format via [`Encoder::write_segments`].
+    pub(crate) fn start_segment(&mut self) {
+        if self.tile_size.is_some() {
+            self.offset = None;
+        }
+    }
+
+    /// Write segments pre-encoded by an encoder with the same format, each started
+    /// with [`Encoder::start_segment`].
+    pub(crate) fn write_segments<'a, W: Write>(
+        &mut self,
+        segments: impl IntoIterator<Item = &'a [u8]>,
+        buf: &mut W,
+    ) -> Result<(), io::Error> {
+        if self.tile_size.is_none() && self.offset != Some(Pos::default()) {
+            Msg::Offset(Pos::default()).encode(buf)?;
+            self.offset = Some(Pos::default());
+            self.auto_offset = false;
+        }
+        for segment in segments {
+            buf.write_all(segment)?;
+        }
+        if self.tile_size.is_some() {
+            self.offset = None;
+            self.auto_offset = true;
+        }
+        Ok(())
+    }
+
+    fn auto_offset<W: Write>(&mut self, offset: Pos, buf: &mut W) -> Result<(), io::Error> {
+        if self.offset != Some(offset) {
+            Msg::Offset(offset).encode(buf)?;
+            self.offset = Some(offset);
+        }
+        self.auto_offset = true;
+        Ok(())
+    }
+
+    /// Absolute coordinates are expected, so undo an offset set by the encoder.
+    fn reset_auto_offset<W: Write>(&mut self, buf: &mut W) -> Result<(), io::Error> {
+        if self.auto_offset {
+            self.auto_offset(Pos::default(), buf)?;
+            self.auto_offset = false;
+        }
+        Ok(())
+    }
+}
+
 impl Response {
-    pub(crate) fn decode(buf: &str) -> Result<(&str, Self), Error> {
-        match buf.as_bytes() {
-            [b'P', b'X', b' ', ..] => {
-                let buf = &buf[3..];
-                let (buf, pos) = Pos::decode(buf)?;
-                let buf = buf.trim_start_matches(char::is_whitespace);
-                let (buf, rgba) = Rgba::decode(buf)?;
-                Ok((buf, Self::Px(pos, rgba)))
-            },
-            [b'S', b'I', b'Z', b'E', b' ', ..] => {
-                let buf = &buf[5..];
-                let (buf, size) = Size::decode(buf)?;
-                Ok((buf, Self::Size(size)))
-            },
+    /// Decodes a single line without its line break. `PX` and `SIZE` responses must be
+    /// well-formed, any other line is a [`ParseErrorKind::UnknownCommand`]. The
+    /// free-form answer to a [`Msg::Help`] is only recognized by the clients, which
+    /// know that it is pending.
+    pub fn decode(line: &[u8]) -> Result<Self, ParseError> {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        let mut fields = fields(line);
+        let resp = match fields.next() {
+            Some(b"PX") => decode_px(&mut fields),
+            Some(b"SIZE") => decode_two_u32(&mut fields).map(|[x, y]| Self::Size(Size { x, y })),
+            _ => Err(ParseErrorKind::UnknownCommand),
+        };
+        let resp = resp.and_then(|resp| match fields.next() {
+            Some(_) => Err(ParseErrorKind::TrailingData),
+            None => Ok(resp),
+        });
+        resp.map_err(|kind| ParseError { kind, line: String::from_utf8_lossy(line).into_owned() })
+    }
+
+    /// Decodes a line of the answer to a [`Msg::Help`]. `None` for the response to the
+    /// `SIZE` request sent after it by the [`Encoder`], which ends the answer.
+    pub(crate) fn decode_help_line(line: &[u8]) -> Option<String> {
+        match Self::decode(line) {
+            Ok(Self::Size(_)) => None,
             _ => {
-                Ok(("", Self::Help(buf.to_string())))
+                let line = line.strip_suffix(b"\r").unwrap_or(line);
+                Some(String::from_utf8_lossy(line).into_owned())
             }
         }
     }
 }
 
+/// Reads [`Response`]s line by line. Lines are decoded straight from the read buffer
+/// and only copied if they span multiple reads.
+pub(crate) struct ResponseReader<R> {
+    read: R,
+    /// Start of a line which didn't fit into the read buffer.
+    partial: Vec<u8>,
+}
+
+impl<R: BufRead> ResponseReader<R> {
+    pub(crate) fn new(read: R) -> Self {
+        Self { read, partial: vec![] }
+    }
+
+    /// Blocks until the next line starts to arrive or the connection is closed.
+    pub(crate) fn wait(&mut self) -> Result<(), Error> {
+        self.read.fill_buf().map_err(Error::Receive)?;
+        Ok(())
+    }
+
+    /// The next response, `None` once the connection is closed.
+    pub(crate) fn next_response(&mut self) -> Result<Option<Response>, Error> {
+        Ok(self.next_line(Response::decode)?.transpose()?)
+    }
+
+    /// The answer to a [`Msg::Help`], its lines joined by `\n`. `None` once the
+    /// connection is closed.
+    pub(crate) fn next_help(&mut self) -> Result<Option<Response>, Error> {
+        let mut help = String::new();
+        while let Some(line) = self.next_line(Response::decode_help_line)? {
+            let Some(line) = line else {
+                return Ok(Some(Response::Help(help)));
+            };
+            if !help.is_empty() {
+                help.push('\n');
+            }
+            help.push_str(&line);
+        }
+        Ok(None)
+    }
+
+    /// Decodes the next line, without its line break, with `decode`.
+    fn next_line<T>(&mut self, decode: impl FnOnce(&[u8]) -> T) -> Result<Option<T>, Error> {
+        self.partial.clear();
+        loop {
+            let buf = self.read.fill_buf().map_err(Error::Receive)?;
+            if buf.is_empty() {
+                if self.partial.is_empty() {
+                    return Ok(None);
+                }
+                return Ok(Some(decode(&self.partial)));
+            }
+            let Some(end) = buf.iter().position(|&b| b == b'\n') else {
+                let len = buf.len();
+                self.partial.extend_from_slice(buf);
+                self.read.consume(len);
+                continue;
+            };
+            let decoded = if self.partial.is_empty() {
+                decode(&buf[..end])
+            } else {
+                self.partial.extend_from_slice(&buf[..end]);
+                decode(&self.partial)
+            };
+            self.read.consume(end + 1);
+            return Ok(Some(decoded));
+        }
+    }
+}
+
+fn decode_cmd(line: &[u8]) -> Result<Msg, ParseErrorKind> {
+    let mut fields = fields(line);
+    let msg = match fields.next() {
+        Some(b"PX") => {
+            let [x, y] = decode_two_u32(&mut fields)?;
+            match fields.next() {
+                Some(col) => Msg::SetPx(Pos { x, y }, Rgba::decode(col)?),
+                None => Msg::GetPx(Pos { x, y }),
+            }
+        }
+        Some(b"OFFSET") => decode_two_u32(&mut fields).map(|[x, y]| Msg::Offset(Pos { x, y }))?,
+        Some(b"SIZE") => Msg::GetSize,
+        Some(b"HELP") => Msg::Help,
+        _ => return Err(ParseErrorKind::UnknownCommand),
+    };
+    match fields.next() {
+        Some(_) => Err(ParseErrorKind::TrailingData),
+        None => Ok(msg),
+    }
+}
+
+fn decode_px<'a>(fields: &mut impl Iterator<Item = &'a [u8]>) -> Result<Response, ParseErrorKind> {
+    let [x, y] = decode_two_u32(fields)?;
+    let col = fields.next().ok_or(ParseErrorKind::MissingField)?;
+    Ok(Response::Px(Pos { x, y }, Rgba::decode(col)?))
+}
+
+/// Non-empty fields of `line` separated by ASCII whitespace.
+fn fields(line: &[u8]) -> impl Iterator<Item = &[u8]> {
+    line.split(u8::is_ascii_whitespace).filter(|field| !field.is_empty())
+}
+
 impl Pos {
     #[inline]
     pub(crate) fn encode<W: Write>(&self, buf: &mut W) -> Result<(), io::Error> {
@@ -65,32 +366,22 @@ impl Pos {
         buf.write_all(itoa_buf.format(self.y).as_bytes())?;
         Ok(())
     }
-
-    pub(crate) fn decode(buf: &str) -> Result<(&str, Self), Error> {
-        let (buf, [x, y]) = decode_two_u32(buf)?;
-        let pos = Pos { x, y };
-        Ok((buf, pos))
-
-    }
 }
 
-impl Size {
-    pub(crate) fn decode(buf: &str) -> Result<(&str, Self), Error> {
-        let (buf, [x, y]) = decode_two_u32(buf)?;
-        let size = Size { x, y };
-        Ok((buf, size))
-    }
+/// Decodes the next two fields as decimal `u32`s.
+pub(crate) fn decode_two_u32<'a>(fields: &mut impl Iterator<Item = &'a [u8]>) -> Result<[u32; 2], ParseErrorKind> {
+    let mut next = || fields.next().ok_or(ParseErrorKind::MissingField).and_then(decode_u32);
+    Ok([next()?, next()?])
 }
 
-pub(crate) fn decode_two_u32(buf: &str) -> Result<(&str, [u32; 2]), Error> {
-    let mut consumed = 0;
-    let mut it = buf.split_ascii_whitespace().map(|s| {
-        consumed  += s.len();
-        s.parse()
-    });
-    let x = it.next().ok_or(Error::MissingData)??;
-    let y = it.next().ok_or(Error::MissingData)??;
-    Ok((&buf[consumed..], [x, y]))
+fn decode_u32(field: &[u8]) -> Result<u32, ParseErrorKind> {
+    if field.is_empty() {
+        return Err(ParseErrorKind::InvalidNumber);
+    }
+    field.iter().try_fold(0_u32, |num, &b| {
+        let digit = (b as char).to_digit(10).ok_or(ParseErrorKind::InvalidNumber)?;
+        num.checked_mul(10).and_then(|num| num.checked_add(digit)).ok_or(ParseErrorKind::InvalidNumber)
+    })
 }
 
 impl Rgba {
@@ -105,26 +396,36 @@ impl Rgba {
         Ok(())
     }
 
-    pub(crate) fn decode(buf: &str) -> Result<(&str, Self), Error> {
-        if buf.len() < 6 {
-            return Err(Error::MissingData);
-        }
-        let r = u8::from_str_radix(&buf[0..2], 16)?;
-        let g = u8::from_str_radix(&buf[2..4], 16)?;
-        let b = u8::from_str_radix(&buf[4..6], 16)?;
-        let a = if buf.len() >= 8 {
-            let a = u8::from_str_radix(&buf[6..8], 16)?;
-            Some(a)
-        } else {
-            None
-        };
-        let rgba = Self {
-            r,
-            g,
-            b,
-            a,
+    /// RGBA bytes, a missing alpha value is treated as fully opaque.
+    #[inline]
+    pub(crate) fn to_bytes(self) -> [u8; 4] {
+        [self.r, self.g, self.b, self.a.unwrap_or(u8::MAX)]
+    }
+
+    /// Decodes `rrggbb`, `rrggbbaa` or the grey value `ww`.
+    pub(crate) fn decode(hex: &[u8]) -> Result<Self, ParseErrorKind> {
+        let byte = |idx: usize| {
+            let nibble = |b: u8| (b as char).to_digit(16).ok_or(ParseErrorKind::InvalidColor);
+            Ok((nibble(hex[idx])? << 4 | nibble(hex[idx + 1])?) as u8)
         };
-        Ok((&buf[8..], rgba))
+        match hex.len() {
+            2 => {
+                let w = byte(0)?;
+                Ok(Self::new(w, w, w, None))
+            }
+            6 => Ok(Self::new(byte(0)?, byte(2)?, byte(4)?, None)),
+            8 => Ok(Self::new(byte(0)?, byte(2)?, byte(4)?, Some(byte(6)?))),
+            _ => Err(ParseErrorKind::InvalidColor),
+        }
+    }
+}
+
+impl FromStr for Rgba {
+    type Err = ParseErrorKind;
+
+    /// Parses `rrggbb`, `rrggbbaa` or the grey value `ww`, optionally prefixed by `#`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::decode(s.strip_prefix('#').unwrap_or(s).as_bytes())
     }
 }
 
@@ -144,8 +445,67 @@ fn fast_byte_to_hex(b: u8) -> [u8; 2] {
 
 #[cfg(test)]
 mod tests {
-    use crate::{Msg, Pos, Rgba};
-    use crate::codec::fast_byte_to_hex;
+    use std::io::BufReader;
+    use proptest::prelude::*;
+    use crate::{Encoder, Encoding, Msg, ParseErrorKind, Pos, Response, Rgba, Size};
+    use crate::codec::{decode_two_u32, fast_byte_to_hex, fields, ResponseReader};
+
+    fn any_pos() -> impl Strategy<Value = Pos> {
+        (any::<u32>(), any::<u32>()).prop_map(|(x, y)| Pos::new(x, y))
+    }
+
+    fn any_rgba() -> impl Strategy<Value = Rgba> {
+        any::<(u8, u8, u8, Option<u8>)>().prop_map(|(r, g, b, a)| Rgba::new(r, g, b, a))
+    }
+
+    fn any_msg() -> impl Strategy<Value = Msg> {
+        prop_oneof![
+            (any_pos(), any_rgba()).prop_map(|(pos, col)| Msg::SetPx(pos, col)),
+            any_pos().prop_map(Msg::GetPx),
+            any_pos().prop_map(Msg::Offset),
+            Just(Msg::GetSize),
+            Just(Msg::Help),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn msg_round_trip(msgs in prop::collection::vec(any_msg(), 0..20), binary: bool) {
+            let encoding = if binary { Encoding::Binary } else { Encoding::Text };
+            let mut buf = vec![];
+            for msg in &msgs {
+                msg.encode_as(encoding, &mut buf).unwrap();
+            }
+            let mut rest = buf.as_slice();
+            for msg in msgs {
+                let (decoded, len) = Msg::decode(rest).unwrap().unwrap();
+                let expected = match msg {
+                    // Binary commands can't tell an opaque alpha value from none
+                    Msg::SetPx(pos, col) if binary && col.a == Some(u8::MAX) && len == 10 => {
+                        Msg::SetPx(pos, Rgba { a: None, ..col })
+                    }
+                    msg => msg,
+                };
+                prop_assert_eq!(decoded, expected);
+                rest = &rest[len..];
+            }
+            prop_assert!(rest.is_empty());
+        }
+
+        #[test]
+        fn px_response_round_trip(pos in any_pos(), col in any_rgba(), sep in "[ \t]{1,3}") {
+            let mut hex = vec![];
+            col.encode(&mut hex).unwrap();
+            let line = format!("PX{sep}{}{sep}{}{sep}{}", pos.x, pos.y, String::from_utf8(hex).unwrap());
+            prop_assert_eq!(Response::decode(line.as_bytes()), Ok(Response::Px(pos, col)));
+        }
+
+        #[test]
+        fn decode_never_panics(line in prop::collection::vec(any::<u8>(), 0..64)) {
+            let _ = Response::decode(&line);
+            let _ = Msg::decode(&line);
+        }
+    }
 
     #[test]
     fn pos_encode() {
@@ -163,6 +523,16 @@ mod tests {
         assert_eq!(&buf, "010b0304".as_bytes())
     }
 
+    #[test]
+    fn rgba_from_str() {
+        assert_eq!("#ff8000".parse(), Ok(Rgba::new(255, 128, 0, None)));
+        assert_eq!("0a0b0c0d".parse(), Ok(Rgba::new(10, 11, 12, Some(13))));
+        assert_eq!("80".parse(), Ok(Rgba::new(128, 128, 128, None)));
+        for invalid in ["+f", "##80", "fffff", "ff00zz", "é0"] {
+            assert_eq!(invalid.parse::<Rgba>(), Err(ParseErrorKind::InvalidColor));
+        }
+    }
+
     #[test]
     fn set_px_encode() {
         let pos = Pos::new(34, 54);
@@ -173,6 +543,69 @@ mod tests {
         assert_eq!(&buf, "PX 34 54 01020f\n".as_bytes());
     }
 
+    #[test]
+    fn set_px_encode_binary() {
+        let pos = Pos::new(258, 3);
+        let col = Rgba::new(1, 2, 15, None);
+        let msg = Msg::SetPx(pos, col);
+        let mut buf = vec![];
+        msg.encode_as(Encoding::Binary, &mut buf).unwrap();
+        assert_eq!(&buf, b"PB\x02\x01\x03\x00\x01\x02\x0f\xff");
+    }
+
+    #[test]
+    fn binary_encode_falls_back_to_text() {
+        let msg = Msg::SetPx(Pos::new(70_000, 1), Rgba::new(1, 2, 15, None));
+        let mut buf = vec![];
+        msg.encode_as(Encoding::Binary, &mut buf).unwrap();
+        assert_eq!(&buf, "PX 70000 1 01020f\n".as_bytes());
+
+        let mut buf = vec![];
+        Msg::GetPx(Pos::new(1, 2)).encode_as(Encoding::Binary, &mut buf).unwrap();
+        assert_eq!(&buf, "PX 1 2\n".as_bytes());
+    }
+
+    #[test]
+    fn offset_encode() {
+        let mut buf = vec![];
+        Msg::Offset(Pos::new(1500, 900)).encode(&mut buf).unwrap();
+        assert_eq!(&buf, "OFFSET 1500 900\n".as_bytes());
+    }
+
+    #[test]
+    fn tile_offsets_encode() {
+        let col = Rgba::new(1, 2, 15, None);
+        let mut encoder = Encoder::default();
+        encoder.set_tile_offsets(Some(100));
+        let mut buf = vec![];
+        for pos in [Pos::new(1500, 900), Pos::new(1599, 901), Pos::new(1600, 901)] {
+            encoder.encode(&Msg::SetPx(pos, col), &mut buf).unwrap();
+        }
+        encoder.encode(&Msg::GetPx(Pos::new(3, 4)), &mut buf).unwrap();
+        let exp = "OFFSET 1500 900\nPX 0 0 01020f\nPX 99 1 01020f\n\
+            OFFSET 1600 900\nPX 0 1 01020f\nOFFSET 0 0\nPX 3 4\n";
+        assert_eq!(String::from_utf8(buf).unwrap(), exp);
+    }
+
+    #[test]
+    fn segments_are_self_contained() {
+        let col = Rgba::new(1, 2, 15, None);
+        let mut pre = Encoder::default();
+        pre.set_tile_offsets(Some(100));
+        let mut segment = vec![];
+        pre.start_segment();
+        pre.encode(&Msg::SetPx(Pos::new(1, 2), col), &mut segment).unwrap();
+
+        let mut encoder = pre.with_same_format();
+        let mut buf = vec![];
+        encoder.encode(&Msg::SetPx(Pos::new(101, 2), col), &mut buf).unwrap();
+        encoder.write_segments([segment.as_slice()], &mut buf).unwrap();
+        encoder.encode(&Msg::SetPx(Pos::new(2, 2), col), &mut buf).unwrap();
+        let exp = "OFFSET 100 0\nPX 1 2 01020f\nOFFSET 0 0\nPX 1 2 01020f\n\
+            OFFSET 0 0\nPX 2 2 01020f\n";
+        assert_eq!(String::from_utf8(buf).unwrap(), exp);
+    }
+
     #[test]
     fn byte_to_hex() {
         for b in 0..255_u8 {
@@ -181,4 +614,77 @@ mod tests {
             assert_eq!(exp, String::from_utf8(hex.to_vec()).unwrap());
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn response_decode() {
+        let px = |line: &[u8]| match Response::decode(line) {
+            Ok(Response::Px(pos, col)) => Ok((pos, col)),
+            Ok(resp) => panic!("unexpected {resp:?}"),
+            Err(err) => Err(err.kind),
+        };
+        assert_eq!(px(b"PX 1 2 ffffff"), Ok((Pos::new(1, 2), Rgba::new(255, 255, 255, None))));
+        assert_eq!(px(b"PX  10\t20 0a0b0c0d\r"), Ok((Pos::new(10, 20), Rgba::new(10, 11, 12, Some(13)))));
+        assert_eq!(px(b"PX 1 2 80"), Ok((Pos::new(1, 2), Rgba::new(128, 128, 128, None))));
+        assert_eq!(px(b"PX 1 2"), Err(ParseErrorKind::MissingField));
+        assert_eq!(px(b"PX 1 2 fffff"), Err(ParseErrorKind::InvalidColor));
+        assert_eq!(px(b"PX 1 2 fffffg"), Err(ParseErrorKind::InvalidColor));
+        assert_eq!(px(b"PX 1 4294967296 ffffff"), Err(ParseErrorKind::InvalidNumber));
+        assert_eq!(px(b"PX 1 2 ffffff 3"), Err(ParseErrorKind::TrailingData));
+
+        assert!(matches!(Response::decode(b"SIZE 1920 1080"), Ok(Response::Size(size)) if size == Size::new(1920, 1080)));
+        let err = Response::decode(b"SIZE -1 2").unwrap_err();
+        assert_eq!(err.line, "SIZE -1 2");
+        for line in [&b"PXL 1"[..], b"ERROR out of bounds", b"HELP PX x y", b""] {
+            let err = Response::decode(line).unwrap_err();
+            assert_eq!(err.kind, ParseErrorKind::UnknownCommand);
+            assert_eq!(err.line.as_bytes(), line);
+        }
+    }
+
+    #[test]
+    fn response_reader_joins_partial_lines() {
+        let data: &[u8] = b"SIZE 800 600\nPX 123 456 ff00ff\nUsage:\r\nSIZE returns the size\nSIZE 800 600\nPX 1 2 ff";
+        let mut reader = ResponseReader::new(BufReader::with_capacity(4, data));
+        assert!(matches!(reader.next_response(), Ok(Some(Response::Size(_)))));
+        assert!(matches!(reader.next_response(), Ok(Some(Response::Px(Pos { x: 123, y: 456 }, _)))));
+        assert!(matches!(reader.next_help(), Ok(Some(Response::Help(help))) if help == "Usage:\nSIZE returns the size"));
+        assert!(matches!(reader.next_response(), Ok(Some(Response::Px(Pos { x: 1, y: 2 }, _)))));
+        assert!(matches!(reader.next_response(), Ok(None)));
+    }
+
+    #[test]
+    fn help_ends_with_size_request() {
+        let mut buf = vec![];
+        Encoder::default().encode(&Msg::Help, &mut buf).unwrap();
+        assert_eq!(&buf, "HELP\nSIZE\n".as_bytes());
+    }
+
+    #[test]
+    fn rgba_decode_lengths() {
+        for len in 0..=10 {
+            let hex = &b"0123456789"[..len];
+            assert_eq!(Rgba::decode(hex).is_ok(), matches!(len, 2 | 6 | 8), "length {len}");
+        }
+    }
+
+    #[test]
+    fn decode_two_u32_edge_cases() {
+        let decode = |line: &str| decode_two_u32(&mut fields(line.as_bytes()));
+        assert_eq!(decode(""), Err(ParseErrorKind::MissingField));
+        assert_eq!(decode("1 "), Err(ParseErrorKind::MissingField));
+        assert_eq!(decode("  12\t 3456  789"), Ok([12, 3456]));
+        assert_eq!(decode("4294967295 0"), Ok([u32::MAX, 0]));
+        assert_eq!(decode("4294967296 0"), Err(ParseErrorKind::InvalidNumber));
+        assert_eq!(decode("+1 2"), Err(ParseErrorKind::InvalidNumber));
+        assert_eq!(decode("1 -2"), Err(ParseErrorKind::InvalidNumber));
+    }
+
+    #[test]
+    fn msg_decode_partial() {
+        assert_eq!(Msg::decode(b"PX 1 2"), Ok(None));
+        assert_eq!(Msg::decode(b"PB\x01\x00\x02"), Ok(None));
+        assert_eq!(Msg::decode(b"SIZE\r\nPX"), Ok(Some((Msg::GetSize, 6))));
+        let err = Msg::decode(b"PXX 1 2\n").unwrap_err();
+        assert_eq!((err.kind, err.line.as_str()), (ParseErrorKind::UnknownCommand, "PXX 1 2"));
+    }
+}
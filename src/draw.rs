@@ -0,0 +1,267 @@
+use imageproc::drawing;
+use imageproc::point::Point;
+use crate::{Msg, Pos, Rect, Rgba, Size};
+use crate::canvas::Canvas;
+
+/// Something the drawing primitives of this module can draw on.
+pub trait DrawTarget {
+    /// Pixels outside of this size are not drawn.
+    fn size(&self) -> Size;
+
+    /// The current color at `pos`, if known.
+    fn get(&self, _pos: Pos) -> Option<Rgba> {
+        None
+    }
+
+    fn set(&mut self, pos: Pos, col: Rgba);
+}
+
+/// Collects drawn pixels as [`Msg::SetPx`] commands, e.g. for [`Client::send_all`](crate::Client::send_all).
+/// Every position is sent at most once, drawing it again replaces the color of its command.
+#[derive(Debug, Clone, Default)]
+pub struct MsgBuffer {
+    size: Size,
+    msgs: Vec<Msg>,
+    /// Index of the command of every pixel in row-major order, plus one. Zero if the
+    /// pixel wasn't drawn yet.
+    indices: Vec<usize>,
+}
+
+impl MsgBuffer {
+    /// Pixels outside of `size`, e.g. [`Client::get_size`](crate::Client::get_size), are dropped.
+    pub fn new(size: Size) -> Self {
+        Self { size, msgs: vec![], indices: vec![0; size.x as usize * size.y as usize] }
+    }
+
+    pub fn msgs(&self) -> &[Msg] {
+        &self.msgs
+    }
+
+    pub fn into_msgs(self) -> Vec<Msg> {
+        self.msgs
+    }
+}
+
+impl DrawTarget for MsgBuffer {
+    fn size(&self) -> Size {
+        self.size
+    }
+
+    fn set(&mut self, pos: Pos, col: Rgba) {
+        if pos.x >= self.size.x || pos.y >= self.size.y {
+            return;
+        }
+        let index = &mut self.indices[pos.y as usize * self.size.x as usize + pos.x as usize];
+        match index.checked_sub(1) {
+            Some(idx) => self.msgs[idx] = Msg::SetPx(pos, col),
+            None => {
+                self.msgs.push(Msg::SetPx(pos, col));
+                *index = self.msgs.len();
+            }
+        }
+    }
+}
+
+impl DrawTarget for Canvas {
+    fn size(&self) -> Size {
+        Canvas::size(self)
+    }
+
+    fn get(&self, pos: Pos) -> Option<Rgba> {
+        Canvas::get(self, pos)
+    }
+
+    fn set(&mut self, pos: Pos, col: Rgba) {
+        Canvas::set(self, pos, col)
+    }
+}
+
+/// Draws a line between `start` and `end`, both inclusive.
+pub fn line(target: &mut impl DrawTarget, start: Pos, end: Pos, col: Rgba) {
+    let start = (start.x as f32, start.y as f32);
+    let end = (end.x as f32, end.y as f32);
+    drawing::draw_line_segment_mut(&mut Adapter(target), start, end, col.into());
+}
+
+pub fn rect(target: &mut impl DrawTarget, rect: Rect, col: Rgba) {
+    if let Some(rect) = to_imageproc_rect(rect) {
+        drawing::draw_hollow_rect_mut(&mut Adapter(target), rect, col.into());
+    }
+}
+
+pub fn fill_rect(target: &mut impl DrawTarget, rect: Rect, col: Rgba) {
+    if let Some(rect) = to_imageproc_rect(rect) {
+        drawing::draw_filled_rect_mut(&mut Adapter(target), rect, col.into());
+    }
+}
+
+pub fn circle(target: &mut impl DrawTarget, center: Pos, radius: u32, col: Rgba) {
+    drawing::draw_hollow_circle_mut(&mut Adapter(target), to_point(center), radius as i32, col.into());
+}
+
+pub fn fill_circle(target: &mut impl DrawTarget, center: Pos, radius: u32, col: Rgba) {
+    drawing::draw_filled_circle_mut(&mut Adapter(target), to_point(center), radius as i32, col.into());
+}
+
+/// Axis-aligned ellipse with the horizontal radius `radii.x` and the vertical radius `radii.y`.
+pub fn ellipse(target: &mut impl DrawTarget, center: Pos, radii: Size, col: Rgba) {
+    let (width, height) = (radii.x as i32, radii.y as i32);
+    drawing::draw_hollow_ellipse_mut(&mut Adapter(target), to_point(center), width, height, col.into());
+}
+
+/// Filled version of [`ellipse`].
+pub fn fill_ellipse(target: &mut impl DrawTarget, center: Pos, radii: Size, col: Rgba) {
+    let (width, height) = (radii.x as i32, radii.y as i32);
+    drawing::draw_filled_ellipse_mut(&mut Adapter(target), to_point(center), width, height, col.into());
+}
+
+/// Outline of the polygon with an implicit edge from the last to the first point.
+pub fn polygon(target: &mut impl DrawTarget, points: &[Pos], col: Rgba) {
+    let Some(&last) = points.last() else {
+        return;
+    };
+    let mut prev = last;
+    for &point in points {
+        line(target, prev, point, col);
+        prev = point;
+    }
+}
+
+/// Filled version of [`polygon`].
+pub fn fill_polygon(target: &mut impl DrawTarget, points: &[Pos], col: Rgba) {
+    let mut poly: Vec<_> = points.iter().map(|&pos| {
+        let (x, y) = to_point(pos);
+        Point::new(x, y)
+    }).collect();
+    // imageproc expects an open path
+    while poly.len() > 1 && poly.first() == poly.last() {
+        poly.pop();
+    }
+    match poly.as_slice() {
+        [] => {}
+        [_] => target.set(points[0], col),
+        poly => drawing::draw_polygon_mut(&mut Adapter(target), poly, col.into()),
+    }
+}
+
+/// Fills the 4-connected area of pixels with the same color as `start`. Needs a
+/// target which knows its current pixels, like [`Canvas`].
+pub fn flood_fill(target: &mut impl DrawTarget, start: Pos, col: Rgba) {
+    let Some(old) = target.get(start) else {
+        return;
+    };
+    if old == col {
+        return;
+    }
+    let size = target.size();
+    let mut stack = vec![start];
+    while let Some(pos) = stack.pop() {
+        if target.get(pos) != Some(old) {
+            continue;
+        }
+        target.set(pos, col);
+        if pos.x > 0 {
+            stack.push(Pos::new(pos.x - 1, pos.y));
+        }
+        if pos.y > 0 {
+            stack.push(Pos::new(pos.x, pos.y - 1));
+        }
+        if pos.x + 1 < size.x {
+            stack.push(Pos::new(pos.x + 1, pos.y));
+        }
+        if pos.y + 1 < size.y {
+            stack.push(Pos::new(pos.x, pos.y + 1));
+        }
+    }
+}
+
+fn to_point(pos: Pos) -> (i32, i32) {
+    (pos.x as i32, pos.y as i32)
+}
+
+fn to_imageproc_rect(rect: Rect) -> Option<imageproc::rect::Rect> {
+    if rect.size.x == 0 || rect.size.y == 0 {
+        return None;
+    }
+    let (x, y) = to_point(rect.pos);
+    Some(imageproc::rect::Rect::at(x, y).of_size(rect.size.x, rect.size.y))
+}
+
+/// Lets imageproc draw on a [`DrawTarget`].
+pub(crate) struct Adapter<'a, T: ?Sized>(pub(crate) &'a mut T);
+
+impl<T: DrawTarget + ?Sized> drawing::Canvas for Adapter<'_, T> {
+    type Pixel = image024::Rgba<u8>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        let size = self.0.size();
+        (size.x, size.y)
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> Self::Pixel {
+        self.0.get(Pos::new(x, y)).map(Into::into).unwrap_or(image024::Rgba([0, 0, 0, 0]))
+    }
+
+    fn draw_pixel(&mut self, x: u32, y: u32, color: Self::Pixel) {
+        self.0.set(Pos::new(x, y), color.into())
+    }
+}
+
+impl From<Rgba> for image024::Rgba<u8> {
+    fn from(col: Rgba) -> Self {
+        image024::Rgba([col.r, col.g, col.b, col.a.unwrap_or(u8::MAX)])
+    }
+}
+
+impl From<image024::Rgba<u8>> for Rgba {
+    /// Fully opaque colors have no alpha value.
+    fn from(col: image024::Rgba<u8>) -> Self {
+        let [r, g, b, a] = col.0;
+        Rgba::new(r, g, b, (a != u8::MAX).then_some(a))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Msg, Pos, Rect, Rgba, Size};
+    use crate::canvas::Canvas;
+    use crate::draw::{self, MsgBuffer};
+
+    #[test]
+    fn outline_and_flood_fill() {
+        let mut canvas = Canvas::new(Size::new(6, 6));
+        draw::rect(&mut canvas, Rect::new(Pos::new(1, 1), Size::new(4, 4)), Rgba::red());
+        assert_eq!(canvas.dirty_count(), 12);
+        draw::flood_fill(&mut canvas, Pos::new(2, 2), Rgba::blue());
+        assert_eq!(canvas.dirty_count(), 16);
+        assert_eq!(canvas.get(Pos::new(3, 3)), Some(Rgba::blue()));
+        assert_eq!(canvas.get(Pos::new(0, 0)), Some(Rgba::new(0, 0, 0, None)));
+    }
+
+    #[test]
+    fn line_is_clipped() {
+        let mut buf = MsgBuffer::new(Size::new(3, 3));
+        draw::line(&mut buf, Pos::new(0, 1), Pos::new(10, 1), Rgba::green());
+        assert_eq!(buf.msgs().len(), 3);
+    }
+
+    #[test]
+    fn shapes_send_pixels_once() {
+        let mut buf = MsgBuffer::new(Size::new(32, 32));
+        draw::fill_circle(&mut buf, Pos::new(15, 15), 10, Rgba::red());
+        draw::ellipse(&mut buf, Pos::new(15, 15), Size::new(12, 6), Rgba::blue());
+        draw::polygon(&mut buf, &[Pos::new(0, 0), Pos::new(31, 31), Pos::new(0, 31)], Rgba::green());
+        let mut positions: Vec<_> = buf.msgs().iter().map(|msg| match msg {
+            Msg::SetPx(pos, _) => *pos,
+            msg => panic!("unexpected {msg:?}"),
+        }).collect();
+        let count = positions.len();
+        positions.sort();
+        positions.dedup();
+        assert_eq!(positions.len(), count);
+        // Later shapes replace the color of earlier ones
+        assert!(buf.msgs().contains(&Msg::SetPx(Pos::new(15, 16), Rgba::red())));
+        assert!(buf.msgs().contains(&Msg::SetPx(Pos::new(0, 0), Rgba::green())));
+        assert!(!buf.msgs().contains(&Msg::SetPx(Pos::new(3, 15), Rgba::red())));
+    }
+}
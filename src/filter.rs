@@ -0,0 +1,89 @@
+/// Changes the pixels of every frame, e.g. of a `CameraWriter`. Closures taking a
+/// `&mut [u8; 4]` RGBA pixel are filters, too.
+pub trait PixelFilter: Send {
+    /// Called before the pixels of the next frame are filtered.
+    fn next_frame(&mut self) {}
+
+    /// Changes an RGBA pixel in place.
+    fn apply(&mut self, px: &mut [u8; 4]);
+}
+
+/// Keeps only one color channel per frame, cycling through red, green and blue.
+#[derive(Clone, Debug, Default)]
+pub struct ChannelCycle {
+    channel: usize,
+}
+
+/// Converts pixels to gray using their luma.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Grayscale;
+
+/// Inverts the color channels, keeping the alpha value.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Invert;
+
+impl PixelFilter for ChannelCycle {
+    fn next_frame(&mut self) {
+        self.channel = (self.channel + 1) % 3;
+    }
+
+    fn apply(&mut self, px: &mut [u8; 4]) {
+        for (idx, val) in px[..3].iter_mut().enumerate() {
+            if idx != self.channel {
+                *val = 0;
+            }
+        }
+    }
+}
+
+impl PixelFilter for Grayscale {
+    fn apply(&mut self, px: &mut [u8; 4]) {
+        // BT.601 weights scaled to 256
+        let luma = (77 * px[0] as u32 + 150 * px[1] as u32 + 29 * px[2] as u32) >> 8;
+        px[..3].fill(luma as u8);
+    }
+}
+
+impl PixelFilter for Invert {
+    fn apply(&mut self, px: &mut [u8; 4]) {
+        px[..3].iter_mut().for_each(|val| *val = u8::MAX - *val);
+    }
+}
+
+impl<F: FnMut(&mut [u8; 4]) + Send> PixelFilter for F {
+    fn apply(&mut self, px: &mut [u8; 4]) {
+        self(px)
+    }
+}
+
+/// Filters a frame of row-major RGBA `pixels`.
+pub fn apply(filter: &mut (impl PixelFilter + ?Sized), pixels: &mut [u8]) {
+    filter.next_frame();
+    for px in pixels.chunks_exact_mut(4) {
+        filter.apply(px.try_into().expect("chunks have 4 bytes"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::{apply, ChannelCycle, Grayscale, Invert};
+
+    #[test]
+    fn filters() {
+        let mut cycle = ChannelCycle::default();
+        let frames: Vec<_> = (0..4).map(|_| {
+            let mut pixels = [10, 20, 30, 40];
+            apply(&mut cycle, &mut pixels);
+            pixels
+        }).collect();
+        assert_eq!(frames, [[0, 20, 0, 40], [0, 0, 30, 40], [10, 0, 0, 40], [0, 20, 0, 40]]);
+
+        let mut pixels = [255, 255, 255, 0, 0, 0, 0, 255];
+        apply(&mut Grayscale, &mut pixels);
+        assert_eq!(pixels, [255, 255, 255, 0, 0, 0, 0, 255]);
+        apply(&mut Invert, &mut pixels);
+        assert_eq!(pixels, [0, 0, 0, 0, 255, 255, 255, 255]);
+        apply(&mut |px: &mut [u8; 4]| px[3] = 1, &mut pixels);
+        assert_eq!(pixels[3], 1);
+    }
+}
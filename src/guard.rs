@@ -0,0 +1,328 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use crate::{Client, Error, Msg, Pos, Response, Rgba, Size};
+use crate::source::Pacer;
+
+/// Defends an image against other clients: each round samples the region with
+/// [`Msg::GetPx`], repaints pixels whose color changed first and spends the rest of
+/// the write budget on repainting the whole image in turn. Damaged pixels cause their
+/// neighbours to be sampled next, as overwrites usually come in blocks.
+///
+/// Pixels are painted opaque, so their colors can be compared with the server's.
+pub struct Guard {
+    pos: Pos,
+    size: Size,
+    /// Intended color of every pixel of the region, `None` if it isn't guarded.
+    intended: Vec<Option<Rgba>>,
+    /// Indices of all guarded pixels in row-major order.
+    guarded: Vec<usize>,
+    options: GuardOptions,
+    /// Pixels known to be overwritten, repainted first.
+    damaged: VecDeque<usize>,
+    /// Pixels to sample before continuing with `sample_cursor`.
+    suspicious: VecDeque<usize>,
+    /// Which queue a pixel is in. Suspicious pixels found to be damaged move to
+    /// `damaged`, leaving a stale entry in `suspicious`.
+    queued: Vec<Queued>,
+    sample_cursor: usize,
+    sample_stride: usize,
+    repaint_cursor: usize,
+    /// Whether pixels outside of the canvas were dropped already.
+    clipped: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Queued {
+    No,
+    Suspicious,
+    Damaged,
+}
+
+/// Options of a [`Guard`].
+#[derive(Clone, Debug)]
+pub struct GuardOptions {
+    budget: usize,
+    read_share: f32,
+    interval: Duration,
+    alpha_threshold: u8,
+}
+
+/// What happened during a [`Guard::round`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RoundStats {
+    pub sampled: usize,
+    /// Sampled pixels which had a different color than intended.
+    pub damaged: usize,
+    /// Repainted pixels which were known to be damaged.
+    pub repaired: usize,
+    /// All repainted pixels, including `repaired` ones.
+    pub painted: usize,
+}
+
+impl GuardOptions {
+    /// Number of commands sent per round. Defaults to `10_000`.
+    pub fn budget(mut self, budget: usize) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Share of the budget spent on [`Msg::GetPx`], clamped to `0.0..=1.0`. Defaults
+    /// to `0.2`.
+    pub fn read_share(mut self, read_share: f32) -> Self {
+        self.read_share = read_share.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Time between the start of two rounds of [`Guard::run`]. Together with the
+    /// budget, this sets the sampling rate. Defaults to 100ms.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Pixels with an alpha value below the threshold are not guarded. Defaults to `1`.
+    pub fn alpha_threshold(mut self, threshold: u8) -> Self {
+        self.alpha_threshold = threshold;
+        self
+    }
+
+    fn reads(&self) -> usize {
+        (self.budget as f32 * self.read_share).round() as usize
+    }
+}
+
+impl Default for GuardOptions {
+    fn default() -> Self {
+        Self {
+            budget: 10_000,
+            read_share: 0.2,
+            interval: Duration::from_millis(100),
+            alpha_threshold: 1,
+        }
+    }
+}
+
+impl Guard {
+    /// Guards the `width * height` row-major RGBA `pixels` placed at `pos`.
+    ///
+    /// # Panics
+    /// If `pixels` doesn't contain exactly `4 * width * height` bytes.
+    pub fn new(pos: Pos, width: u32, height: u32, pixels: &[u8], options: GuardOptions) -> Self {
+        assert_eq!(pixels.len(), 4 * width as usize * height as usize, "image size mismatch");
+        let intended: Vec<_> = pixels.chunks_exact(4).map(|px| {
+            (px[3] >= options.alpha_threshold).then(|| Rgba::new(px[0], px[1], px[2], None))
+        }).collect();
+        let guarded: Vec<_> = (0..intended.len()).filter(|&idx| intended[idx].is_some()).collect();
+        Self {
+            pos,
+            size: Size::new(width, height),
+            queued: vec![Queued::No; intended.len()],
+            intended,
+            sample_stride: sample_stride(guarded.len()),
+            guarded,
+            options,
+            damaged: VecDeque::new(),
+            suspicious: VecDeque::new(),
+            sample_cursor: 0,
+            repaint_cursor: 0,
+            clipped: false,
+        }
+    }
+
+    /// Number of pixels known to be overwritten which weren't repainted yet.
+    pub fn damaged_count(&self) -> usize {
+        self.damaged.len()
+    }
+
+    /// Runs rounds paced by [`GuardOptions::interval`] until an error occurs.
+    pub fn run(&mut self, client: &mut Client) -> Result<(), Error> {
+        let mut pacer = Pacer::new();
+        loop {
+            self.round(client)?;
+            pacer.wait(self.options.interval);
+        }
+    }
+
+    /// Repaints and samples pixels once, using up the budget. The first round stops
+    /// guarding pixels outside of the canvas, as servers don't respond to reading them.
+    pub fn round(&mut self, client: &mut Client) -> Result<RoundStats, Error> {
+        if !self.clipped {
+            self.clip(client.get_size()?);
+        }
+        let (msgs, mut stats) = self.plan();
+        let responses = client.send_all(&msgs)?;
+        stats.damaged = self.check(&responses);
+        Ok(stats)
+    }
+
+    /// Stops guarding pixels outside of a canvas of size `canvas`.
+    fn clip(&mut self, canvas: Size) {
+        for idx in 0..self.intended.len() {
+            let pos = self.abs_pos(idx);
+            if pos.x >= canvas.x || pos.y >= canvas.y {
+                self.intended[idx] = None;
+            }
+        }
+        self.guarded.retain(|&idx| self.intended[idx].is_some());
+        self.sample_stride = sample_stride(self.guarded.len());
+        self.sample_cursor = 0;
+        self.repaint_cursor = 0;
+        self.clipped = true;
+    }
+
+    /// Commands for one round: repaints followed by samples.
+    fn plan(&mut self) -> (Vec<Msg>, RoundStats) {
+        let mut stats = RoundStats::default();
+        if self.guarded.is_empty() {
+            return (vec![], stats);
+        }
+        let reads = self.options.reads();
+        let writes = self.options.budget - reads;
+        let mut msgs = Vec::with_capacity(self.options.budget);
+
+        while msgs.len() < writes {
+            let Some(idx) = self.damaged.pop_front() else {
+                break;
+            };
+            self.queued[idx] = Queued::No;
+            msgs.push(self.set_px(idx));
+            stats.repaired += 1;
+        }
+        while msgs.len() < writes {
+            let idx = self.guarded[self.repaint_cursor];
+            self.repaint_cursor = (self.repaint_cursor + 1) % self.guarded.len();
+            msgs.push(self.set_px(idx));
+        }
+        stats.painted = msgs.len();
+
+        while msgs.len() < writes + reads {
+            let idx = match self.suspicious.pop_front() {
+                Some(idx) if self.queued[idx] != Queued::Suspicious => continue,
+                Some(idx) => {
+                    self.queued[idx] = Queued::No;
+                    idx
+                }
+                None => {
+                    let idx = self.guarded[self.sample_cursor];
+                    self.sample_cursor = (self.sample_cursor + self.sample_stride) % self.guarded.len();
+                    idx
+                }
+            };
+            msgs.push(Msg::GetPx(self.abs_pos(idx)));
+        }
+        stats.sampled = reads;
+        (msgs, stats)
+    }
+
+    /// Queues damaged pixels for repainting and their neighbours for sampling.
+    /// Returns the number of damaged pixels.
+    fn check(&mut self, responses: &[Response]) -> usize {
+        let mut damaged = 0;
+        for resp in responses {
+            let Response::Px(pos, col) = resp else {
+                continue;
+            };
+            let Some(idx) = self.index(*pos) else {
+                continue;
+            };
+            let Some(intended) = self.intended[idx] else {
+                continue;
+            };
+            if (col.r, col.g, col.b) == (intended.r, intended.g, intended.b) {
+                continue;
+            }
+            damaged += 1;
+            if self.queued[idx] != Queued::Damaged {
+                self.queued[idx] = Queued::Damaged;
+                self.damaged.push_back(idx);
+            }
+            let (x, y) = (pos.x - self.pos.x, pos.y - self.pos.y);
+            let neighbours = [
+                x.checked_sub(1).map(|x| (x, y)),
+                y.checked_sub(1).map(|y| (x, y)),
+                Some((x + 1, y)),
+                Some((x, y + 1)),
+            ];
+            for (x, y) in neighbours.into_iter().flatten() {
+                let Some(idx) = self.index(Pos::new(self.pos.x + x, self.pos.y + y)) else {
+                    continue;
+                };
+                if self.intended[idx].is_some() && self.queued[idx] == Queued::No {
+                    self.queued[idx] = Queued::Suspicious;
+                    self.suspicious.push_back(idx);
+                }
+            }
+        }
+        damaged
+    }
+
+    fn set_px(&self, idx: usize) -> Msg {
+        let col = self.intended[idx].expect("only guarded pixels are painted");
+        Msg::SetPx(self.abs_pos(idx), col)
+    }
+
+    fn abs_pos(&self, idx: usize) -> Pos {
+        let width = self.size.x as usize;
+        Pos::new(self.pos.x + (idx % width) as u32, self.pos.y + (idx / width) as u32)
+    }
+
+    /// Index of the absolute `pos`, `None` if it is outside the region.
+    fn index(&self, pos: Pos) -> Option<usize> {
+        let x = pos.x.checked_sub(self.pos.x).filter(|&x| x < self.size.x)?;
+        let y = pos.y.checked_sub(self.pos.y).filter(|&y| y < self.size.y)?;
+        Some(y as usize * self.size.x as usize + x as usize)
+    }
+}
+
+/// A stride coprime to `len`, so stepping through `len` pixels with it visits every
+/// pixel once while spreading consecutive samples over the image.
+fn sample_stride(len: usize) -> usize {
+    let gcd = |mut a: usize, mut b: usize| {
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        a
+    };
+    // Close to the golden ratio of `len`
+    let start = (len as f64 * 0.618) as usize;
+    (start.max(1)..len).find(|&stride| gcd(stride, len) == 1).unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Msg, Pos, Response, Rgba};
+    use crate::guard::{sample_stride, Guard, GuardOptions};
+
+    #[test]
+    fn repaints_damaged_pixels_first() {
+        let pixels = [255, 0, 0, 255].repeat(9);
+        let options = GuardOptions::default().budget(4).read_share(0.5);
+        let mut guard = Guard::new(Pos::new(10, 10), 3, 3, &pixels, options);
+
+        let (msgs, stats) = guard.plan();
+        assert_eq!((stats.painted, stats.sampled), (2, 2));
+        assert!(matches!(msgs[..], [Msg::SetPx(..), Msg::SetPx(..), Msg::GetPx(_), Msg::GetPx(_)]));
+
+        let damaged = guard.check(&[
+            Response::Px(Pos::new(12, 12), Rgba::blue()),
+            Response::Px(Pos::new(11, 11), Rgba::red()),
+        ]);
+        assert_eq!(damaged, 1);
+        let (msgs, stats) = guard.plan();
+        assert_eq!(stats.repaired, 1);
+        assert!(matches!(msgs[0], Msg::SetPx(Pos { x: 12, y: 12 }, _)));
+        // Neighbours of the damaged pixel are sampled first
+        assert!(matches!(msgs[2], Msg::GetPx(Pos { x: 11, y: 12 })));
+        assert!(matches!(msgs[3], Msg::GetPx(Pos { x: 12, y: 11 })));
+    }
+
+    #[test]
+    fn stride_visits_every_pixel() {
+        for len in [1, 2, 9, 10, 97] {
+            let stride = sample_stride(len);
+            let mut visited: Vec<_> = (0..len).map(|k| k * stride % len).collect();
+            visited.sort_unstable();
+            assert_eq!(visited, (0..len).collect::<Vec<_>>());
+        }
+    }
+}
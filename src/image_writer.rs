@@ -1,21 +1,54 @@
 use std::fs::File;
 use std::io;
-use std::io::{BufReader, Write};
+use std::io::BufReader;
 use std::path::Path;
 use std::time::Duration;
 use image::codecs::gif::GifDecoder;
-use image::{AnimationDecoder, ImageError};
+use image::{imageops, AnimationDecoder, ImageError, ImageFormat, RgbaImage};
 use thiserror::Error;
-use crate::{Msg, Pos, Rgba};
+use crate::{Encoder, Pos, Rect, Rgba, Size};
+use crate::source::{Frame, FrameEncoder, FrameSource, SourceFrame, StreamOptions};
+
+pub use image::imageops::FilterType;
+pub use crate::source::{Alpha, FrameMode};
 
 pub struct GifWriter {
-    msg_buf: Vec<Frame>
+    msg_buf: Vec<(Frame, Duration)>,
+    encoder: Encoder,
+    /// Next frame returned by [`FrameSource::next_frame`].
+    position: usize,
+}
+
+/// A still image in any format supported by the enabled features of the `image` crate
+/// (**features = ["png", "jpeg", "bmp", "webp"]**).
+pub struct ImageWriter {
+    frame: Frame,
+    encoder: Encoder,
+    /// Whether [`FrameSource::next_frame`] returned the image since the last `None`.
+    sent: bool,
 }
 
+/// Options for loading images via [`GifWriter::load`] and [`ImageWriter::load`].
+/// Images are first cropped, then scaled and finally placed at the offset.
 #[derive(Clone, Debug)]
-pub(crate) struct Frame {
-    msgs: Vec<Msg>,
-    delay: Duration
+pub struct LoadOptions {
+    encoder: Encoder,
+    crop: Option<Rect>,
+    scale: Scale,
+    filter: FilterType,
+    stream: StreamOptions,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Scale {
+    /// Keep the size of the (cropped) image.
+    #[default]
+    Original,
+    /// Scale to exactly this size.
+    To(Size),
+    /// Scale to the largest size fitting into this size while keeping the aspect
+    /// ratio, e.g. to fit [`Client::get_size`](crate::Client::get_size).
+    Fit(Size),
 }
 
 #[derive(Debug, Error)]
@@ -23,56 +56,180 @@ pub enum Error {
     #[error("Unable to open file")]
     Open(#[from] io::Error),
     #[error("Unable to decode image")]
-    Decode(#[from] ImageError)
+    Decode(#[from] ImageError),
+    #[error("Unable to encode image")]
+    Encode(#[source] ImageError)
+}
+
+impl LoadOptions {
+    /// Images are pre-encoded in the format of `encoder`, e.g.
+    /// [`Client::encoder`](crate::Client::encoder). They can only be sent by
+    /// clients using the same format.
+    pub fn new(encoder: &Encoder) -> Self {
+        Self {
+            encoder: encoder.with_same_format(),
+            crop: None,
+            scale: Scale::default(),
+            filter: FilterType::Triangle,
+            stream: StreamOptions::default(),
+        }
+    }
+
+    /// Position of the top left corner of the image on the board.
+    pub fn offset(mut self, offset: Pos) -> Self {
+        self.stream = self.stream.offset(offset);
+        self
+    }
+
+    /// Only draw this part of the source image.
+    pub fn crop(mut self, rect: Rect) -> Self {
+        self.crop = Some(rect);
+        self
+    }
+
+    pub fn scale(mut self, scale: Scale) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Resampling filter used for scaling.
+    pub fn filter(mut self, filter: FilterType) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Pixels with an alpha value below the threshold are not sent. Defaults to `1`,
+    /// i.e. only fully transparent pixels are skipped.
+    pub fn alpha_threshold(mut self, threshold: u8) -> Self {
+        self.stream = self.stream.alpha_threshold(threshold);
+        self
+    }
+
+    /// How pixels which are sent but not fully opaque are handled.
+    pub fn alpha(mut self, alpha: Alpha) -> Self {
+        self.stream = self.stream.alpha(alpha);
+        self
+    }
+
+    /// [`FrameMode`] for the frames of animations.
+    pub fn frame_mode(mut self, frame_mode: FrameMode) -> Self {
+        self.stream = self.stream.frame_mode(frame_mode);
+        self
+    }
+
+    fn transform(&self, img: &RgbaImage) -> RgbaImage {
+        let cropped;
+        let img = match self.crop {
+            Some(Rect { pos, size }) => {
+                cropped = imageops::crop_imm(img, pos.x, pos.y, size.x, size.y).to_image();
+                &cropped
+            }
+            None => img,
+        };
+        let (width, height) = img.dimensions();
+        let target = match self.scale {
+            Scale::Original => return img.clone(),
+            Scale::To(size) => size,
+            Scale::Fit(size) => {
+                let factor = f64::min(size.x as f64 / width as f64, size.y as f64 / height as f64);
+                let scaled = |len: u32| ((len as f64 * factor).round() as u32).max(1);
+                Size::new(scaled(width), scaled(height))
+            }
+        };
+        if target == Size::new(width, height) {
+            return img.clone();
+        }
+        imageops::resize(img, target.x, target.y, self.filter)
+    }
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self::new(&Encoder::default())
+    }
 }
 
 impl GifWriter {
-    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+    /// Loads the gif and pre-encodes all frames according to the [`LoadOptions`].
+    pub fn load(path: impl AsRef<Path>, options: &LoadOptions) -> Result<Self, Error> {
         let reader = BufReader::new(File::open(path)?);
         let decoder = GifDecoder::new(reader)?;
-        let msg_buf = msg_frames(decoder)?;
+        let msg_buf = msg_frames(decoder, options)?;
         Ok(Self {
             msg_buf,
+            encoder: options.encoder.clone(),
+            position: 0,
         })
     }
 
-    pub(crate) fn frames(&self) -> &[Frame] {
-        &self.msg_buf
+    pub fn encoder(&self) -> &Encoder {
+        &self.encoder
     }
 }
 
-impl Frame {
-    pub(crate) fn encode<W: Write>(&self, buf: &mut W) -> Result<(), io::Error> {
-        for msg in &self.msgs {
-            msg.encode(buf)?;
-        }
-        Ok(())
+impl ImageWriter {
+    /// Loads the image, guessing the format from the file extension, and pre-encodes
+    /// it according to the [`LoadOptions`].
+    pub fn load(path: impl AsRef<Path>, options: &LoadOptions) -> Result<Self, Error> {
+        let format = ImageFormat::from_path(&path)?;
+        let reader = BufReader::new(File::open(path)?);
+        let img = image::load(reader, format)?.into_rgba8();
+        let img = options.transform(&img);
+        let frame = FrameEncoder::new(&options.stream)
+            .encode(&options.encoder, img.width(), img.height(), img.as_raw());
+        Ok(Self {
+            frame,
+            encoder: options.encoder.clone(),
+            sent: false,
+        })
     }
 
-    pub fn delay(&self) -> Duration {
-        self.delay
+    pub fn encoder(&self) -> &Encoder {
+        &self.encoder
     }
 }
 
-fn msg_frames(decoder: GifDecoder<BufReader<File>>) -> Result<Vec<Frame>, ImageError> {
+impl FrameSource for GifWriter {
+    /// Returns the pre-encoded frames of one loop of the gif.
+    fn next_frame(&mut self) -> Result<Option<SourceFrame<'_>>, crate::Error> {
+        let Some((frame, delay)) = self.msg_buf.get(self.position) else {
+            self.position = 0;
+            return Ok(None);
+        };
+        self.position += 1;
+        Ok(Some(SourceFrame::encoded(&self.encoder, frame, *delay)))
+    }
+}
+
+impl FrameSource for ImageWriter {
+    /// Returns the pre-encoded image once.
+    fn next_frame(&mut self) -> Result<Option<SourceFrame<'_>>, crate::Error> {
+        self.sent = !self.sent;
+        Ok(self.sent.then(|| SourceFrame::encoded(&self.encoder, &self.frame, Duration::ZERO)))
+    }
+}
+
+fn msg_frames(decoder: GifDecoder<BufReader<File>>, options: &LoadOptions) -> Result<Vec<(Frame, Duration)>, ImageError> {
     let mut msg_frames = vec![];
+    let mut frame_encoder = FrameEncoder::new(&options.stream);
     for frame in decoder.into_frames() {
         let frame = frame?;
-        let mut msgs = Vec::with_capacity(frame.buffer().len());
-        for (y, row) in frame.buffer().rows().enumerate() {
-            for (x, px) in row.enumerate() {
-                let pos = Pos::new(x as u32, y as u32);
-                let col = Rgba::from(px);
-                msgs.push(Msg::SetPx(pos, col));
-            }
-        }
         let (num, denum) = frame.delay().numer_denom_ms();
         let delay = Duration::from_secs_f64(num as f64 / (denum as f64 * 1000.0));
-        msg_frames.push(Frame { msgs, delay });
+        let img = options.transform(frame.buffer());
+        let frame = frame_encoder.encode(&options.encoder, img.width(), img.height(), img.as_raw());
+        msg_frames.push((frame, delay));
     }
     Ok(msg_frames)
 }
 
+/// Saves an image, e.g. of [`Client::read_region`](crate::Client::read_region), as PNG.
+/// Needs **features = ["png"]**.
+#[cfg(feature = "png")]
+pub fn save_png(image: &RgbaImage, path: impl AsRef<Path>) -> Result<(), Error> {
+    image.save_with_format(path, ImageFormat::Png).map_err(Error::Encode)
+}
+
 impl From<&image::Rgba<u8>> for Rgba {
     fn from(v: &image::Rgba<u8>) -> Self {
         Self {
@@ -83,3 +240,19 @@ impl From<&image::Rgba<u8>> for Rgba {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use image::RgbaImage;
+    use crate::{Pos, Rect, Size};
+    use crate::image_writer::{LoadOptions, Scale};
+
+    #[test]
+    fn crop_and_fit() {
+        let img = RgbaImage::new(40, 30);
+        let options = LoadOptions::default()
+            .crop(Rect::new(Pos::new(10, 10), Size::new(20, 10)))
+            .scale(Scale::Fit(Size::new(100, 100)));
+        assert_eq!(options.transform(&img).dimensions(), (100, 50));
+    }
+}
@@ -1,9 +1,23 @@
-use std::io::{self, BufRead, BufReader, BufWriter, Lines, Write};
+use std::io;
 use std::net::{TcpStream, ToSocketAddrs};
-use std::num::ParseIntError;
+use std::sync::mpsc;
 use thiserror::Error;
+use source::{FrameSource, StreamOptions};
+use split::{ClientReader, ClientWriter};
 
+pub mod canvas;
+pub mod draw;
+pub mod filter;
+pub mod guard;
 mod codec;
+pub use codec::Encoder;
+pub mod pool;
+pub mod reconnect;
+pub mod source;
+pub mod split;
+pub mod text;
+#[cfg(feature = "async")]
+pub mod async_client;
 #[cfg(feature = "image")]
 pub mod image_writer;
 #[cfg(feature = "camera")]
@@ -11,18 +25,49 @@ pub mod camera;
 
 #[cfg(feature = "capture")]
 pub mod screen_capture;
+#[cfg(feature = "server")]
+pub mod server;
 
 pub struct Client {
-    write: BufWriter<TcpStream>,
-    read: Lines<BufReader<TcpStream>>,
+    writer: ClientWriter,
+    reader: ClientReader,
 }
 
 impl Client {
+    /// Responses are read by a background thread, see [`ClientReader`].
     pub fn connect(addr: impl ToSocketAddrs) -> Result<Self, Error> {
         let stream = TcpStream::connect(addr).map_err(Error::Connect)?;
-        let write = BufWriter::new(stream.try_clone().unwrap());
-        let read = BufReader::new(stream).lines();
-        Ok(Self { write, read })
+        let (helps, help_rx) = mpsc::channel();
+        let reader = ClientReader::spawn(stream.try_clone().map_err(Error::Connect)?, help_rx)?;
+        Ok(Self { writer: ClientWriter::new(stream, helps), reader })
+    }
+
+    /// Split into independent halves, e.g. to send requests on one thread while
+    /// handling their responses on another.
+    pub fn split(self) -> (ClientWriter, ClientReader) {
+        (self.writer, self.reader)
+    }
+
+    /// Set the [`Encoding`] used for all subsequently sent pixel commands.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.writer.set_encoding(encoding);
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.writer.encoding()
+    }
+
+    /// Automatically emit [`Msg::Offset`] commands so that [`Msg::SetPx`] coordinates
+    /// are sent relative to the `tile_size` sized tile they lie in. See
+    /// [`Encoder::set_tile_offsets`].
+    pub fn set_tile_offsets(&mut self, tile_size: Option<u32>) {
+        self.writer.set_tile_offsets(tile_size);
+    }
+
+    /// The [`Encoder`] used by this client, e.g. for pre-encoding a `GifWriter` in the
+    /// same format.
+    pub fn encoder(&self) -> &Encoder {
+        self.writer.encoder()
     }
 
     pub fn get_size(&mut self) -> Result<Size, Error> {
@@ -73,45 +118,64 @@ impl Client {
     /// Use [`Client::send`] or manual [`Client::flush`].
     #[inline]
     pub fn send_buffered(&mut self, msg: Msg) -> Result<(), Error> {
-        msg.encode(&mut self.write).map_err(Error::SendCmd)?;
-        Ok(())
+        self.writer.send_buffered(msg)
     }
 
-    /// Send a gif loaded via the [`GifWriter`] API. Needs **features = ["image"]**.
+    /// Reads the pixels of `rect` from the server, pipelining [`Msg::GetPx`] in batches.
+    /// Pixels are placed by the position in the response, pixels without one, e.g. outside
+    /// of the canvas, stay fully transparent. Needs **features = ["image"]**.
     #[cfg(feature = "image")]
-    pub fn send_gif(&mut self, gif: &image_writer::GifWriter) -> Result<(), Error> {
-        for frame in gif.frames() {
-            frame.encode(&mut self.write).map_err(Error::SendCmd)?;
-            self.flush()?;
-            std::thread::sleep(frame.delay());
+    pub fn read_region(&mut self, rect: Rect) -> Result<image::RgbaImage, Error> {
+        const BATCH_SIZE: usize = 16 * 1024;
+        let mut image = image::RgbaImage::new(rect.size.x, rect.size.y);
+        // Servers don't respond to positions outside of the canvas
+        let canvas = self.get_size()?;
+        let width = rect.size.x.min(canvas.x.saturating_sub(rect.pos.x));
+        let height = rect.size.y.min(canvas.y.saturating_sub(rect.pos.y));
+        let positions = (0..height)
+            .flat_map(|y| (0..width).map(move |x| Pos::new(rect.pos.x + x, rect.pos.y + y)));
+        let mut msgs = Vec::with_capacity(BATCH_SIZE);
+        let mut positions = positions.peekable();
+        while positions.peek().is_some() {
+            msgs.clear();
+            msgs.extend(positions.by_ref().take(BATCH_SIZE).map(Msg::GetPx));
+            for resp in self.send_all(&msgs)? {
+                let Response::Px(pos, col) = resp else {
+                    return Err(Error::WrongResponse);
+                };
+                let x = pos.x.checked_sub(rect.pos.x).filter(|&x| x < rect.size.x);
+                let y = pos.y.checked_sub(rect.pos.y).filter(|&y| y < rect.size.y);
+                if let (Some(x), Some(y)) = (x, y) {
+                    image.put_pixel(x, y, image::Rgba(col.to_bytes()));
+                }
+            }
         }
-        Ok(())
+        Ok(image)
     }
 
-    #[cfg(feature = "capture")]
-    pub fn send_capture(&mut self, screen_writer: &mut screen_capture::ScreenWriter) -> Result<(), Error> {
-        screen_writer.capture(&mut self.write)?;
-        self.flush()?;
-        Ok(())
+    /// Sends all frames of `source`, pacing them according to their delay. Returns
+    /// once the source is exhausted. Pre-encoded frames, e.g. of a `GifWriter`, must be
+    /// encoded in the format of [`Client::encoder`].
+    pub fn stream(&mut self, source: &mut impl FrameSource, options: &StreamOptions) -> Result<(), Error> {
+        let encoder = self.encoder().clone();
+        source::stream_frames(source, options, &encoder, |frame| self.send_segments([frame.data()]))
     }
 
-    #[cfg(feature = "camera")]
-    pub fn send_camera_capture(&mut self, camera_writer: &mut camera::CameraWriter) -> Result<(), Error> {
-        camera_writer.capture(&mut self.write)?;
-        self.flush()?;
-        Ok(())
+    /// Writes and flushes segments pre-encoded in the format of [`Client::encoder`].
+    pub(crate) fn send_segments<'a>(&mut self, segments: impl IntoIterator<Item = &'a [u8]>) -> Result<(), Error> {
+        let writer = &mut self.writer;
+        writer.encoder.write_segments(segments, &mut writer.write).map_err(Error::SendCmd)?;
+        self.flush()
     }
 
     #[inline]
     fn recv(&mut self) -> Result<Response, Error> {
-        let line = self.read.next().ok_or(Error::MissingData)?.map_err(Error::Receive)?;
-        let line = line.as_str();
-        let (_rest, resp) = Response::decode(line)?;
-        Ok(resp)
+        let (_id, resp) = self.reader.recv();
+        resp
     }
 
     pub fn flush(&mut self) -> Result<(), Error> {
-        self.write.flush().map_err(Error::SendCmd)
+        self.writer.flush()
     }
 }
 
@@ -125,12 +189,14 @@ pub enum Error {
     Receive(#[source] io::Error),
     #[error("Missing data in response")]
     MissingData,
-    #[error("Unable to decode rgba color")]
-    RgbaDecode(#[from] ParseIntError),
+    #[error("Unable to parse response")]
+    Parse(#[from] ParseError),
     #[error("The msg to sent expects no response. Use send.")]
     NoResponseExpected,
     #[error("Server sent wrong response")]
     WrongResponse,
+    #[error("Pre-encoded data uses a different encoding or tile size than the client")]
+    FormatMismatch,
     #[cfg(feature = "capture")]
     #[error("Unable to send screen capture")]
     ScreenCapture(#[from] screen_capture::Error),
@@ -139,28 +205,93 @@ pub enum Error {
     CameraCapture(#[from] camera::Error)
 }
 
-#[derive(Debug, Clone, Copy)]
+/// A response or command line which couldn't be parsed.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{kind} in line {line:?}")]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    /// The offending line, invalid UTF-8 replaced.
+    pub line: String,
+}
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    #[error("Missing field")]
+    MissingField,
+    #[error("Invalid number")]
+    InvalidNumber,
+    #[error("Invalid color")]
+    InvalidColor,
+    #[error("Unexpected trailing data")]
+    TrailingData,
+    #[error("Unknown command")]
+    UnknownCommand,
+}
+
+/// Wire encoding of pixel commands.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum Encoding {
+    /// ASCII `PX x y rrggbb\n` commands.
+    #[default]
+    Text,
+    /// Binary `PB` commands: little-endian `u16` x and y followed by RGBA bytes.
+    /// Commands other than [`Msg::SetPx`] and coordinates which don't fit into
+    /// a `u16` fall back to [`Encoding::Text`].
+    Binary,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Msg {
     SetPx(Pos, Rgba),
     GetPx(Pos),
+    /// Coordinates of subsequent [`Msg::SetPx`] and [`Msg::GetPx`] commands are
+    /// relative to this offset.
+    Offset(Pos),
     GetSize,
     Help
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum Response {
+    /// Color of a pixel. Servers may send `rrggbb`, `rrggbbaa` or a grey value `ww`.
     Px(Pos, Rgba),
     Size(Size),
+    /// Free-form answer to a [`Msg::Help`], its lines joined by `\n`.
     Help(String)
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
 pub struct Size {
     pub x: u32,
     pub y: u32
 }
 
-#[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
+impl Size {
+    pub fn new(x: u32, y: u32) -> Self {
+        Self {
+            x,
+            y,
+        }
+    }
+}
+
+/// Rectangle with its top left corner at `pos`.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Rect {
+    pub pos: Pos,
+    pub size: Size
+}
+
+impl Rect {
+    pub fn new(pos: Pos, size: Size) -> Self {
+        Self {
+            pos,
+            size,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Ord, PartialOrd, Eq, PartialEq)]
 pub struct Pos {
     pub x: u32,
     pub y: u32
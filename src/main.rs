@@ -1,60 +1,404 @@
-use std::collections::btree_map::Entry;
-use std::{fs, mem};
-use barrel::{Client, Msg, Pos, Rgba};
-use barrel::camera::CameraWriter;
-use barrel::image_writer::GifWriter;
-use barrel::screen_capture::{Mode, ScreenWriter};
-// use barrel::image_writer::GifWriter;
-
-fn main() {
-
-    let mut client = Client::connect("192.168.120.119:1234").unwrap();
-
-    // let size = client.get_size().unwrap();
-    // let mut gif_writer = GifWriter::load("images/bad-apple.gif").unwrap();
-    // loop {
-    //     client.send_gif(&mut gif_writer).unwrap();
-    // }
-
-    // loop {
-    //     for x in 0 .. size.x {
-    //         for y in 0..size.y {
-    //             if (x < 50 || x < size.x / 2 + 50) && y < 50 {
-    //                 continue;
-    //             }
-    //             let pos = Pos::new(x, y);
-    //             let col = Rgba::new(229, 65, 251, None);
-    //             client.send_buffered(Msg::SetPx(pos, col)).unwrap();
-    //         }
-    //     }
-    //     client.flush().unwrap();
-    // }
-
-    let mut cam_writer = CameraWriter::new(0).unwrap();
-    
-    loop {
-        client.send_camera_capture(&mut cam_writer).unwrap()
-    } 
-
-    // let mut screen_writer = ScreenWriter::new(0, Mode::SendDiff).unwrap();
-    // loop {
-    //     client.send_capture(&mut screen_writer).unwrap()
-    // }
-
-    // let mut msgs = vec![];
-    // for x in 0..600 {
-    //     for y in 0..600 {
-    //         let pos = Pos::new(x, y);
-    //         let col = Rgba::blue();
-    //         msgs.push(Msg::SetPx(pos, col));
-    //     }
-    // }
-
-    // loop {
-    //     for entry in fs::read_dir("images").unwrap() {
-    //         let gif_writer = GifWriter::load(entry.unwrap().path()).unwrap();
-    //         client.send_gif(&gif_writer).unwrap();
-    //     }
-    // }
+use std::error::Error;
+#[cfg(any(feature = "image", feature = "ttf"))]
+use std::path::PathBuf;
+use std::process::ExitCode;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+#[cfg(feature = "camera")]
+use barrel::camera::{CameraDevice, CameraOptions, CameraWriter, PixelFormat};
+#[cfg(feature = "camera")]
+use barrel::filter::{ChannelCycle, Grayscale, Invert, PixelFilter};
+use barrel::draw::{self, MsgBuffer};
+#[cfg(feature = "image")]
+use barrel::image_writer::{GifWriter, ImageWriter, LoadOptions, Scale};
+use barrel::pool::{ClientPool, Sharding};
+#[cfg(feature = "capture")]
+use barrel::screen_capture::ScreenWriter;
+#[cfg(any(feature = "image", feature = "capture", feature = "camera"))]
+use barrel::source::{FrameMode, StreamOptions};
+use barrel::text::{self, Align, TextStyle};
+use barrel::{Encoding, Pos, Rect, Rgba, Size};
+
+/// Pixelflut client
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(flatten)]
+    connection: ConnectionArgs,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+struct ConnectionArgs {
+    /// Address of the server
+    #[arg(long, short = 'H', global = true, default_value = "localhost:1337")]
+    host: String,
+    /// Number of connections the pixels are distributed over
+    #[arg(long, short, global = true, default_value_t = 1)]
+    connections: usize,
+    /// Distribution of the pixels over the connections
+    #[arg(long, global = true, value_enum, default_value_t = ShardingArg::Interleave)]
+    sharding: ShardingArg,
+    #[arg(long, global = true, value_enum, default_value_t = EncodingArg::Text)]
+    encoding: EncodingArg,
+    /// Send `OFFSET` commands for tiles of this size to shorten the coordinates
+    #[arg(long, global = true, value_name = "SIZE")]
+    tile_size: Option<u32>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Send a gif
+    #[cfg(feature = "image")]
+    Gif {
+        path: PathBuf,
+        #[command(flatten)]
+        placement: PlacementArgs,
+        #[command(flatten)]
+        frames: FrameArgs,
+        /// Play the gif endlessly
+        #[arg(long = "loop")]
+        repeat: bool,
+    },
+    /// Send a still image
+    #[cfg(feature = "image")]
+    Image {
+        path: PathBuf,
+        #[command(flatten)]
+        placement: PlacementArgs,
+        /// Keep sending the image to repair overwritten pixels
+        #[arg(long)]
+        repeat: bool,
+    },
+    /// Stream the screen
+    #[cfg(feature = "capture")]
+    Screen {
+        /// Index of the display to capture
+        #[arg(long, default_value_t = 0)]
+        display: usize,
+        #[arg(long, short, value_parser = parse_pos, default_value = "0,0")]
+        offset: Pos,
+        #[command(flatten)]
+        frames: FrameArgs,
+    },
+    /// Stream a camera
+    #[cfg(feature = "camera")]
+    Camera {
+        /// Path of the camera or its index, as in `/dev/video<INDEX>`
+        #[arg(long, value_parser = parse_camera_device, default_value = "0")]
+        device: CameraDevice,
+        /// Requested resolution, as WxH
+        #[arg(long, value_parser = parse_size, default_value = "1280x720")]
+        resolution: Size,
+        /// Requested frame rate
+        #[arg(long)]
+        fps: Option<u32>,
+        /// Requested pixel format
+        #[arg(long, value_enum, default_value_t = PixelFormatArg::Mjpg)]
+        format: PixelFormatArg,
+        /// Filter applied to every frame
+        #[arg(long, value_enum, default_value_t = FilterArg::None)]
+        filter: FilterArg,
+        #[arg(long, short, value_parser = parse_pos, default_value = "0,0")]
+        offset: Pos,
+        #[command(flatten)]
+        frames: FrameArgs,
+    },
+    /// Fill a rectangle with a color
+    Fill {
+        /// Hex color: rrggbb, rrggbbaa or ww
+        color: Rgba,
+        #[arg(long, short, value_parser = parse_pos, default_value = "0,0")]
+        offset: Pos,
+        /// Size of the rectangle, defaults to the rest of the canvas
+        #[arg(long, value_parser = parse_size)]
+        size: Option<Size>,
+        /// Keep filling to repair overwritten pixels
+        #[arg(long)]
+        repeat: bool,
+    },
+    /// Write text
+    Text {
+        text: String,
+        #[arg(long, short, value_parser = parse_pos, default_value = "0,0")]
+        offset: Pos,
+        /// Font size in pixels
+        #[arg(long, default_value_t = 8)]
+        size: u32,
+        #[arg(long, default_value = "ffffff")]
+        color: Rgba,
+        #[arg(long)]
+        background: Option<Rgba>,
+        /// Alignment of the text relative to the offset
+        #[arg(long, value_enum, default_value_t = AlignArg::Left)]
+        align: AlignArg,
+        /// TrueType font instead of the built-in pixel font
+        #[cfg(feature = "ttf")]
+        #[arg(long)]
+        font: Option<PathBuf>,
+    },
+    /// Print the size of the canvas
+    Size,
+}
+
+#[cfg(feature = "image")]
+#[derive(Args)]
+struct PlacementArgs {
+    /// Position of the top left corner, as X,Y
+    #[arg(long, short, value_parser = parse_pos, default_value = "0,0")]
+    offset: Pos,
+    /// Scale to this size, as WxH
+    #[arg(long, value_parser = parse_size, conflicts_with = "fit")]
+    scale: Option<Size>,
+    /// Scale to fit the canvas, keeping the aspect ratio
+    #[arg(long)]
+    fit: bool,
+}
+
+#[cfg(any(feature = "image", feature = "capture", feature = "camera"))]
+#[derive(Args)]
+struct FrameArgs {
+    /// Only send pixels which changed since the previous frame
+    #[arg(long)]
+    delta: bool,
+    /// Send every N-th frame completely in delta mode
+    #[arg(long, value_name = "N", requires = "delta")]
+    keyframe_interval: Option<usize>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ShardingArg {
+    Interleave,
+    Rows,
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum EncodingArg {
+    Text,
+    Binary,
+}
+
+#[cfg(feature = "camera")]
+#[derive(Clone, Copy, ValueEnum)]
+enum PixelFormatArg {
+    Mjpg,
+    Yuyv,
+    Rgb,
+}
+
+#[cfg(feature = "camera")]
+#[derive(Clone, Copy, ValueEnum)]
+enum FilterArg {
+    /// True color
+    None,
+    /// Only one of red, green and blue per frame
+    ChannelCycle,
+    Grayscale,
+    Invert,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum AlignArg {
+    Left,
+    Center,
+    Right,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            let mut source = err.source();
+            while let Some(err) = source {
+                eprintln!("  caused by: {err}");
+                source = err.source();
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    let mut pool = cli.connection.connect()?;
+    match cli.command {
+        #[cfg(feature = "image")]
+        Command::Gif { path, placement, frames, repeat } => {
+            let options = placement.load_options(&mut pool)?.frame_mode(frames.mode());
+            let mut gif = GifWriter::load(path, &options)?;
+            loop {
+                pool.stream(&mut gif, &StreamOptions::default())?;
+                if !repeat {
+                    break;
+                }
+            }
+        }
+        #[cfg(feature = "image")]
+        Command::Image { path, placement, repeat } => {
+            let options = placement.load_options(&mut pool)?;
+            let mut image = ImageWriter::load(path, &options)?;
+            loop {
+                pool.stream(&mut image, &StreamOptions::default())?;
+                if !repeat {
+                    break;
+                }
+            }
+        }
+        #[cfg(feature = "capture")]
+        Command::Screen { display, offset, frames } => {
+            let mut screen = ScreenWriter::new(display)?;
+            pool.stream(&mut screen, &frames.stream_options(offset))?;
+        }
+        #[cfg(feature = "camera")]
+        Command::Camera { device, resolution, fps, format, filter, offset, frames } => {
+            let mut options = CameraOptions::default().device(device).size(resolution).format(format.into());
+            if let Some(fps) = fps {
+                options = options.fps(fps);
+            }
+            let mut camera = CameraWriter::open(&options)?;
+            camera.set_filter(filter.into());
+            pool.stream(&mut camera, &frames.stream_options(offset))?;
+        }
+        Command::Fill { color, offset, size, repeat } => {
+            let canvas = pool.get_size()?;
+            let size = size.unwrap_or(Size::new(
+                canvas.x.saturating_sub(offset.x),
+                canvas.y.saturating_sub(offset.y),
+            ));
+            let mut msgs = MsgBuffer::new(canvas);
+            draw::fill_rect(&mut msgs, Rect::new(offset, size), color);
+            loop {
+                pool.send_all(msgs.msgs())?;
+                if !repeat {
+                    break;
+                }
+            }
+        }
+        Command::Text { text, offset, size, color, background, align, #[cfg(feature = "ttf")] font } => {
+            let style = TextStyle::default()
+                .size(size)
+                .color(color)
+                .background(background)
+                .align(align.into());
+            #[cfg(feature = "ttf")]
+            let style = match font {
+                Some(path) => style.font(text::Font::ttf(std::fs::read(&path)?)
+                    .ok_or_else(|| format!("{} is not a TrueType font", path.display()))?),
+                None => style,
+            };
+            let mut msgs = MsgBuffer::new(pool.get_size()?);
+            text::text(&mut msgs, offset, &text, &style);
+            pool.send_all(msgs.msgs())?;
+        }
+        Command::Size => {
+            let size = pool.get_size()?;
+            println!("{}x{}", size.x, size.y);
+        }
+    }
+    // Waits until the server processed all commands before closing the connections
+    pool.sync()?;
+    Ok(())
+}
+
+impl ConnectionArgs {
+    fn connect(&self) -> Result<ClientPool, barrel::Error> {
+        let mut pool = ClientPool::connect(self.host.as_str(), self.connections)?;
+        pool.set_threaded(pool.connections() > 1);
+        pool.set_sharding(match self.sharding {
+            ShardingArg::Interleave => Sharding::Interleave,
+            ShardingArg::Rows => Sharding::Rows,
+        });
+        pool.set_encoding(match self.encoding {
+            EncodingArg::Text => Encoding::Text,
+            EncodingArg::Binary => Encoding::Binary,
+        });
+        pool.set_tile_offsets(self.tile_size);
+        Ok(pool)
+    }
+}
+
+#[cfg(feature = "image")]
+impl PlacementArgs {
+    fn load_options(&self, pool: &mut ClientPool) -> Result<LoadOptions, barrel::Error> {
+        let scale = match (self.scale, self.fit) {
+            (Some(size), _) => Scale::To(size),
+            (None, true) => {
+                let canvas = pool.get_size()?;
+                Scale::Fit(Size::new(canvas.x.saturating_sub(self.offset.x), canvas.y.saturating_sub(self.offset.y)))
+            }
+            (None, false) => Scale::Original,
+        };
+        Ok(LoadOptions::new(pool.encoder()).offset(self.offset).scale(scale))
+    }
+}
+
+#[cfg(any(feature = "image", feature = "capture", feature = "camera"))]
+impl FrameArgs {
+    fn mode(&self) -> FrameMode {
+        match self.delta {
+            true => FrameMode::Delta { keyframe_interval: self.keyframe_interval },
+            false => FrameMode::Full,
+        }
+    }
+
+    #[cfg(any(feature = "capture", feature = "camera"))]
+    fn stream_options(&self, offset: Pos) -> StreamOptions {
+        StreamOptions::default().offset(offset).frame_mode(self.mode())
+    }
+}
+
+#[cfg(feature = "camera")]
+impl From<PixelFormatArg> for PixelFormat {
+    fn from(format: PixelFormatArg) -> Self {
+        match format {
+            PixelFormatArg::Mjpg => PixelFormat::Mjpg,
+            PixelFormatArg::Yuyv => PixelFormat::Yuyv,
+            PixelFormatArg::Rgb => PixelFormat::Rgb,
+        }
+    }
+}
+
+#[cfg(feature = "camera")]
+impl From<FilterArg> for Option<Box<dyn PixelFilter>> {
+    fn from(filter: FilterArg) -> Self {
+        match filter {
+            FilterArg::None => None,
+            FilterArg::ChannelCycle => Some(Box::new(ChannelCycle::default())),
+            FilterArg::Grayscale => Some(Box::new(Grayscale)),
+            FilterArg::Invert => Some(Box::new(Invert)),
+        }
+    }
+}
+
+impl From<AlignArg> for Align {
+    fn from(align: AlignArg) -> Self {
+        match align {
+            AlignArg::Left => Align::Left,
+            AlignArg::Center => Align::Center,
+            AlignArg::Right => Align::Right,
+        }
+    }
+}
+
+fn parse_pos(s: &str) -> Result<Pos, String> {
+    let (x, y) = s.split_once(',').ok_or("expected X,Y")?;
+    Ok(Pos::new(parse_u32(x)?, parse_u32(y)?))
+}
+
+fn parse_size(s: &str) -> Result<Size, String> {
+    let (x, y) = s.split_once('x').ok_or("expected WxH")?;
+    Ok(Size::new(parse_u32(x)?, parse_u32(y)?))
+}
+
+#[cfg(feature = "camera")]
+fn parse_camera_device(s: &str) -> Result<CameraDevice, String> {
+    Ok(match s.parse() {
+        Ok(index) => CameraDevice::Index(index),
+        Err(_) => CameraDevice::Path(s.into()),
+    })
+}
+
+fn parse_u32(s: &str) -> Result<u32, String> {
+    s.trim().parse().map_err(|_| format!("invalid number {s:?}"))
+}
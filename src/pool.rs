@@ -0,0 +1,259 @@
+use std::mem;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::panic;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use crate::{Client, Encoder, Encoding, Error, Msg, Response, Size};
+use crate::source::{self, Frame, FrameSource, StreamOptions};
+
+/// Multiple [`Client`] connections to the same server which share the pixels of a frame.
+pub struct ClientPool {
+    connections: Vec<Connection>,
+    /// Copy of the encoder of all connections, which may be owned by workers.
+    encoder: Encoder,
+    sharding: Sharding,
+}
+
+/// How the [`Msg::SetPx`] commands of a frame are distributed over the connections
+/// of a [`ClientPool`].
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Sharding {
+    /// [`ClientPool::send_all`] sends the `i`-th pixel on connection `i % connections`.
+    /// Frames are interleaved by rows instead, as their rows are pre-encoded as a whole.
+    #[default]
+    Interleave,
+    /// Every connection sends a horizontal band of rows of the frame.
+    Rows,
+}
+
+/// Work for a single connection, returning the responses it received.
+type Job = Box<dyn FnOnce(&mut Client) -> Result<Vec<Response>, Error> + Send>;
+
+enum Connection {
+    Local(Client),
+    Worker(Worker),
+}
+
+/// Thread owning the [`Client`] of a threaded [`ClientPool`]. It runs jobs in order
+/// until its job sender is dropped and then hands back the client.
+struct Worker {
+    jobs: Sender<Job>,
+    results: Receiver<Result<Vec<Response>, Error>>,
+    thread: Option<JoinHandle<Client>>,
+}
+
+impl ClientPool {
+    /// Opens `connections` connections (at least one) to `addr`.
+    pub fn connect(addr: impl ToSocketAddrs, connections: usize) -> Result<Self, Error> {
+        let addrs: Vec<SocketAddr> = addr.to_socket_addrs().map_err(Error::Connect)?.collect();
+        let connections = (0..connections.max(1))
+            .map(|_| Client::connect(&addrs[..]).map(Connection::Local))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { connections, encoder: Encoder::default(), sharding: Sharding::default() })
+    }
+
+    pub fn connections(&self) -> usize {
+        self.connections.len()
+    }
+
+    pub fn set_sharding(&mut self, sharding: Sharding) {
+        self.sharding = sharding;
+    }
+
+    /// Send the shard of every connection on its own thread. The threads are kept
+    /// until the pool is dropped or this is disabled again.
+    pub fn set_threaded(&mut self, threaded: bool) {
+        self.connections = mem::take(&mut self.connections).into_iter().map(|conn| match (conn, threaded) {
+            (Connection::Local(client), true) => Connection::Worker(Worker::spawn(client)),
+            (Connection::Worker(worker), false) => Connection::Local(worker.into_client()),
+            (conn, _) => conn,
+        }).collect();
+    }
+
+    /// See [`Client::set_encoding`].
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoder.set_encoding(encoding);
+        let _ = self.run_each(|_| Box::new(move |client| {
+            client.set_encoding(encoding);
+            Ok(vec![])
+        }));
+    }
+
+    /// See [`Client::set_tile_offsets`].
+    pub fn set_tile_offsets(&mut self, tile_size: Option<u32>) {
+        self.encoder.set_tile_offsets(tile_size);
+        let _ = self.run_each(|_| Box::new(move |client| {
+            client.set_tile_offsets(tile_size);
+            Ok(vec![])
+        }));
+    }
+
+    /// Encoder shared by all connections, e.g. to pre-encode images for the pool.
+    pub fn encoder(&self) -> &Encoder {
+        &self.encoder
+    }
+
+    pub fn get_size(&mut self) -> Result<Size, Error> {
+        let responses = self.run_first(Box::new(|client| Ok(vec![Response::Size(client.get_size()?)])))?;
+        match responses[..] {
+            [Response::Size(size)] => Ok(size),
+            _ => unreachable!("the job returns the size"),
+        }
+    }
+
+    /// Waits until the server processed all commands sent on every connection, by
+    /// requesting the size on each of them.
+    pub fn sync(&mut self) -> Result<(), Error> {
+        self.run_each(|_| Box::new(|client| {
+            client.get_size()?;
+            Ok(vec![])
+        }))?;
+        Ok(())
+    }
+
+    /// Distributes the [`Msg::SetPx`] commands according to the [`Sharding`] and
+    /// flushes every connection. [`Msg::Offset`] is sent on all connections, messages
+    /// which expect a response are sent on the first connection, whose responses
+    /// are returned.
+    pub fn send_all(&mut self, msgs: &[Msg]) -> Result<Vec<Response>, Error> {
+        let mut shards = self.shard(msgs);
+        let mut responses = self.run_each(|idx| {
+            let shard = mem::take(&mut shards[idx]);
+            Box::new(move |client| client.send_all(&shard))
+        })?;
+        Ok(responses.swap_remove(0))
+    }
+
+    /// Sends all frames of `source` like [`Client::stream`], distributing the rows of
+    /// every frame according to the [`Sharding`].
+    pub fn stream(&mut self, source: &mut impl FrameSource, options: &StreamOptions) -> Result<(), Error> {
+        let encoder = self.encoder.clone();
+        source::stream_frames(source, options, &encoder, |frame| self.send_frame(frame))
+    }
+
+    fn send_frame(&mut self, frame: &Frame) -> Result<(), Error> {
+        let connections = self.connections.len();
+        let band_height = frame.rows().count() / connections + 1;
+        let sharding = self.sharding;
+        self.run_each(|idx| {
+            // Frames share their data, so this doesn't copy the rows
+            let frame = frame.clone();
+            Box::new(move |client| {
+                let rows = frame.rows();
+                match sharding {
+                    Sharding::Interleave => client.send_segments(rows.skip(idx).step_by(connections))?,
+                    Sharding::Rows => client.send_segments(rows.skip(idx * band_height).take(band_height))?,
+                }
+                Ok(vec![])
+            })
+        })?;
+        Ok(())
+    }
+
+    fn shard(&self, msgs: &[Msg]) -> Vec<Vec<Msg>> {
+        let connections = self.connections.len();
+        let mut shards = vec![vec![]; connections];
+        let band_height = match self.sharding {
+            Sharding::Interleave => 0,
+            Sharding::Rows => {
+                let max_y = msgs.iter().filter_map(|msg| match msg {
+                    Msg::SetPx(pos, _) => Some(pos.y),
+                    _ => None
+                }).max().unwrap_or(0);
+                max_y as usize / connections + 1
+            }
+        };
+        let mut px_count = 0;
+        for msg in msgs {
+            match msg {
+                Msg::SetPx(pos, _) => {
+                    let idx = match self.sharding {
+                        Sharding::Interleave => px_count % connections,
+                        Sharding::Rows => pos.y as usize / band_height,
+                    };
+                    shards[idx].push(*msg);
+                    px_count += 1;
+                }
+                Msg::Offset(_) => shards.iter_mut().for_each(|shard| shard.push(*msg)),
+                _ => shards[0].push(*msg),
+            }
+        }
+        shards
+    }
+
+    /// Runs the job created by `job` for every connection, on the worker threads if
+    /// the pool is threaded, and waits for all of them.
+    fn run_each(&mut self, mut job: impl FnMut(usize) -> Job) -> Result<Vec<Vec<Response>>, Error> {
+        let mut results: Vec<_> = self.connections.iter_mut().enumerate().map(|(idx, conn)| match conn {
+            Connection::Local(client) => Some(job(idx)(client)),
+            Connection::Worker(worker) => {
+                worker.start(job(idx));
+                None
+            }
+        }).collect();
+        // Results of all workers are collected even after an error to keep them in sync
+        for (conn, result) in self.connections.iter_mut().zip(&mut results) {
+            if let Connection::Worker(worker) = conn {
+                *result = Some(worker.finish());
+            }
+        }
+        results.into_iter().map(|result| result.expect("every job finished")).collect()
+    }
+
+    /// Runs `job` for the first connection only.
+    fn run_first(&mut self, job: Job) -> Result<Vec<Response>, Error> {
+        match &mut self.connections[0] {
+            Connection::Local(client) => job(client),
+            Connection::Worker(worker) => {
+                worker.start(job);
+                worker.finish()
+            }
+        }
+    }
+}
+
+impl Worker {
+    fn spawn(mut client: Client) -> Self {
+        let (jobs, job_rx) = mpsc::channel::<Job>();
+        let (result_tx, results) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name("barrel-pool".to_string())
+            .spawn(move || {
+                for job in job_rx {
+                    if result_tx.send(job(&mut client)).is_err() {
+                        break;
+                    }
+                }
+                client
+            })
+            .expect("failed to spawn pool thread");
+        Self { jobs, results, thread: Some(thread) }
+    }
+
+    /// Starts `job`, whose result is returned by the next [`Worker::finish`].
+    fn start(&self, job: Job) {
+        // Only fails if the thread panicked, which `finish` reports
+        let _ = self.jobs.send(job);
+    }
+
+    /// Waits for the result of the oldest started job. Panics of the job are
+    /// propagated.
+    fn finish(&mut self) -> Result<Vec<Response>, Error> {
+        match self.results.recv() {
+            Ok(result) => result,
+            Err(_) => match self.thread.take().expect("panics are only propagated once").join() {
+                Ok(_) => unreachable!("the thread only stops once the pool drops the job sender"),
+                Err(panic) => panic::resume_unwind(panic),
+            },
+        }
+    }
+
+    fn into_client(self) -> Client {
+        let Worker { jobs, thread, .. } = self;
+        drop(jobs);
+        match thread.expect("threads of panicked workers are not reused").join() {
+            Ok(client) => client,
+            Err(panic) => panic::resume_unwind(panic),
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::thread;
+use std::time::Duration;
+use crate::{Client, Encoding, Error, Msg, Response, Size};
+use crate::source::{self, FrameSource, StreamOptions};
+
+/// Number of messages which are flushed together by [`ReconnectingClient::send_frame`].
+/// After a reconnect, sending resumes at the start of the interrupted chunk.
+const CHUNK_SIZE: usize = 4096;
+
+/// Wraps a [`Client`] and transparently reconnects to the same address when the
+/// connection fails.
+pub struct ReconnectingClient {
+    addrs: Vec<SocketAddr>,
+    client: Client,
+    backoff: Backoff,
+    encoding: Encoding,
+    tile_size: Option<u32>,
+    on_event: Option<EventCallback>,
+}
+
+type EventCallback = Box<dyn FnMut(&ReconnectEvent<'_>) + Send>;
+
+/// Exponential backoff between reconnection attempts.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    /// Delay before the first attempt.
+    pub initial: Duration,
+    /// Upper bound for the delay between attempts.
+    pub max: Duration,
+    /// Factor by which the delay grows after every failed attempt. Factors below `1.0`
+    /// and NaN keep the delay constant.
+    pub factor: f64,
+    /// Give up after this many failed attempts. `None` retries forever.
+    pub max_retries: Option<u32>,
+}
+
+/// Reported to the callback registered with [`ReconnectingClient::on_reconnect`].
+#[derive(Debug)]
+pub enum ReconnectEvent<'a> {
+    /// The connection failed with the contained error.
+    Disconnected(&'a Error),
+    /// A reconnect is attempted after waiting for `delay`.
+    Retry { attempt: u32, delay: Duration },
+    /// Successfully reconnected after `attempts` attempts.
+    Reconnected { attempts: u32 },
+    /// [`Backoff::max_retries`] were exhausted.
+    GaveUp,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(50),
+            max: Duration::from_secs(5),
+            factor: 2.0,
+            max_retries: None,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay of the attempt after one which waited for `delay`.
+    fn next_delay(&self, delay: Duration) -> Duration {
+        // `max` ignores NaN
+        let factor = self.factor.max(1.0);
+        Duration::try_from_secs_f64(delay.as_secs_f64() * factor)
+            .unwrap_or(self.max)
+            .min(self.max)
+    }
+}
+
+impl ReconnectingClient {
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Self, Error> {
+        let addrs: Vec<SocketAddr> = addr.to_socket_addrs().map_err(Error::Connect)?.collect();
+        let client = Client::connect(&addrs[..])?;
+        Ok(Self {
+            addrs,
+            client,
+            backoff: Backoff::default(),
+            encoding: Encoding::default(),
+            tile_size: None,
+            on_event: None,
+        })
+    }
+
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Register a callback which is notified about disconnects and reconnection attempts.
+    pub fn on_reconnect(mut self, callback: impl FnMut(&ReconnectEvent<'_>) + Send + 'static) -> Self {
+        self.on_event = Some(Box::new(callback));
+        self
+    }
+
+    /// The currently used connection. Errors of direct calls are not retried.
+    pub fn client(&mut self) -> &mut Client {
+        &mut self.client
+    }
+
+    /// See [`Client::set_encoding`]. Also applied after reconnecting.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+        self.client.set_encoding(encoding);
+    }
+
+    /// See [`Client::set_tile_offsets`]. Also applied after reconnecting.
+    pub fn set_tile_offsets(&mut self, tile_size: Option<u32>) {
+        self.tile_size = tile_size;
+        self.client.set_tile_offsets(tile_size);
+    }
+
+    pub fn get_size(&mut self) -> Result<Size, Error> {
+        self.retry(|client, _| client.get_size())
+    }
+
+    /// Flushes the internal buffer after sending.
+    pub fn send(&mut self, msg: Msg) -> Result<(), Error> {
+        self.retry(|client, _| client.send(msg))
+    }
+
+    /// See [`Client::send_all`]. On a reconnect, all messages are sent again.
+    pub fn send_all(&mut self, msgs: &[Msg]) -> Result<Vec<Response>, Error> {
+        self.retry(|client, _| client.send_all(msgs))
+    }
+
+    /// Send the messages of a frame, flushing after every chunk of messages. If the
+    /// connection fails, the frame is resumed at the interrupted chunk on the new
+    /// connection, preceded by the last [`Msg::Offset`] of the frame.
+    pub fn send_frame(&mut self, msgs: &[Msg]) -> Result<(), Error> {
+        let mut offset = None;
+        for chunk in msgs.chunks(CHUNK_SIZE) {
+            self.retry(|client, resumed| {
+                if let (true, Some(offset)) = (resumed, offset) {
+                    client.send_buffered(offset)?;
+                }
+                for msg in chunk {
+                    client.send_buffered(*msg)?;
+                }
+                client.flush()
+            })?;
+            if let Some(last) = chunk.iter().rev().find(|msg| matches!(msg, Msg::Offset(_))) {
+                offset = Some(*last);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends all frames of `source` like [`Client::stream`]. A frame interrupted by a
+    /// reconnect is sent again completely.
+    pub fn stream(&mut self, source: &mut impl FrameSource, options: &StreamOptions) -> Result<(), Error> {
+        let encoder = self.client.encoder().clone();
+        source::stream_frames(source, options, &encoder, |frame| {
+            self.retry(|client, _| client.send_segments([frame.data()]))
+        })
+    }
+
+    /// Calls `f` until it succeeds or fails with an error unrelated to the connection.
+    /// The second argument of `f` is true if it is called again after a reconnect.
+    fn retry<T>(&mut self, mut f: impl FnMut(&mut Client, bool) -> Result<T, Error>) -> Result<T, Error> {
+        let mut resumed = false;
+        loop {
+            match f(&mut self.client, resumed) {
+                Err(err) if is_connection_error(&err) => self.reconnect(err)?,
+                res => return res,
+            }
+            resumed = true;
+        }
+    }
+
+    fn reconnect(&mut self, cause: Error) -> Result<(), Error> {
+        self.emit(ReconnectEvent::Disconnected(&cause));
+        let mut delay = self.backoff.initial;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            if self.backoff.max_retries.is_some_and(|max| attempt > max) {
+                self.emit(ReconnectEvent::GaveUp);
+                return Err(cause);
+            }
+            self.emit(ReconnectEvent::Retry { attempt, delay });
+            thread::sleep(delay);
+            if let Ok(mut client) = Client::connect(&self.addrs[..]) {
+                client.set_encoding(self.encoding);
+                client.set_tile_offsets(self.tile_size);
+                self.client = client;
+                self.emit(ReconnectEvent::Reconnected { attempts: attempt });
+                return Ok(());
+            }
+            delay = self.backoff.next_delay(delay);
+        }
+    }
+
+    fn emit(&mut self, event: ReconnectEvent<'_>) {
+        if let Some(on_event) = &mut self.on_event {
+            on_event(&event);
+        }
+    }
+}
+
+fn is_connection_error(err: &Error) -> bool {
+    matches!(err, Error::SendCmd(_) | Error::Receive(_) | Error::MissingData)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use crate::reconnect::{Backoff, ReconnectEvent, ReconnectingClient};
+
+    #[test]
+    fn backoff_delays() {
+        let backoff = Backoff { initial: Duration::from_millis(10), max: Duration::from_millis(50), ..Backoff::default() };
+        let delays: Vec<_> = std::iter::successors(Some(backoff.initial), |&delay| Some(backoff.next_delay(delay)))
+            .take(5)
+            .map(|delay| delay.as_millis())
+            .collect();
+        assert_eq!(delays, [10, 20, 40, 50, 50]);
+
+        for factor in [-1.0, 0.5, f64::NAN] {
+            let backoff = Backoff { factor, ..backoff.clone() };
+            assert_eq!(backoff.next_delay(Duration::from_millis(10)), Duration::from_millis(10));
+        }
+        let backoff = Backoff { factor: f64::INFINITY, max: Duration::MAX, ..backoff };
+        assert_eq!(backoff.next_delay(Duration::from_secs(u64::MAX / 2)), Duration::MAX);
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let events = Arc::new(Mutex::new(vec![]));
+        let recorded = events.clone();
+        let backoff = Backoff { initial: Duration::from_millis(1), max_retries: Some(2), ..Backoff::default() };
+        let mut client = ReconnectingClient::connect(addr).unwrap()
+            .with_backoff(backoff)
+            .on_reconnect(move |event| recorded.lock().unwrap().push(format!("{event:?}")));
+        // Closes the connection and refuses new ones
+        drop(listener);
+
+        assert!(client.get_size().is_err());
+        let events = events.lock().unwrap();
+        assert!(events[0].starts_with("Disconnected"));
+        assert_eq!(events[1..], [
+            format!("{:?}", ReconnectEvent::Retry { attempt: 1, delay: Duration::from_millis(1) }),
+            format!("{:?}", ReconnectEvent::Retry { attempt: 2, delay: Duration::from_millis(2) }),
+            format!("{:?}", ReconnectEvent::GaveUp),
+        ]);
+    }
+}
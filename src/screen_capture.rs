@@ -1,13 +1,14 @@
-use std::{io, mem};
-use std::io::Write;
-use captrs::{Bgr8, CaptureError, Capturer};
+use std::time::Duration;
+use captrs::{CaptureError, Capturer};
 use thiserror::Error;
-use crate::{Msg, Pos, Rgba};
+use crate::Rgba;
+use crate::source::{FrameSource, SourceFrame};
 
+/// Captures the screen as a [`FrameSource`]. Use [`FrameMode::Delta`](crate::source::FrameMode::Delta)
+/// to only send changed pixels.
 pub struct ScreenWriter {
     capturer: Capturer,
-    mode: Mode,
-    previous: Vec<captrs::Bgr8>
+    pixels: Vec<u8>
 }
 
 #[derive(Error, Debug)]
@@ -16,69 +17,24 @@ pub enum Error {
     CapturerCreate(String),
     #[error("Unable to capture screen")]
     Capture(CaptureError),
-    #[error("Unable to send PX command")]
-    SendPixel(#[from] io::Error)
-}
-
-pub enum Mode {
-    SendAll,
-    SendDiff
 }
 
 impl ScreenWriter {
-    pub fn new(capture_src: usize, mode: Mode) -> Result<Self, Error> {
+    pub fn new(capture_src: usize) -> Result<Self, Error> {
         let capturer = Capturer::new(capture_src).map_err(Error::CapturerCreate)?;
-        Ok(Self { capturer, mode, previous: vec![] })
-    }
-
-    pub fn capture<W: Write>(&mut self, buf: &mut W) -> Result<(), Error> {
-        match self.mode {
-            Mode::SendAll => self.capture_all(buf),
-            Mode::SendDiff => self.capture_diff(buf)
-        }
+        Ok(Self { capturer, pixels: vec![] })
     }
+}
 
-    fn capture_all<W:Write>(&mut self, buf: &mut W) -> Result<(), Error> {
-        let (dimx, _dimy) = self.capturer.geometry();
-        self.capturer.capture_store_frame().map_err(Error::Capture)?;
-        let img = self.capturer.get_stored_frame().expect("Frame was stored earlier");
-        for (y, row ) in  img.chunks_exact(dimx as usize).enumerate() {
-            for (x, px) in row.iter().enumerate() {
-                let pos = Pos::new(x as u32, y as u32);
-                let col = px.into();
-                Msg::SetPx(pos, col).encode(buf)?;
-            }
-        }
-        Ok(())
-    }
-
-
-    fn capture_diff<W:Write>(&mut self, buf: &mut W) -> Result<(), Error> {
-        let (dimx, _dimy) = self.capturer.geometry();
-        let prev = match self.capturer.get_stored_frame() {
-            Some(prev) => prev,
-            None => {
-                self.capturer.capture_frame().map_err(Error::Capture)?;
-                let prev = self.capturer.get_stored_frame().expect("frame was stored");
-                prev
-            }
-        };
-        self.previous.clear();
-        self.previous.extend_from_slice(prev);
-
+impl FrameSource for ScreenWriter {
+    /// Captures the next frame, never returns `None`.
+    fn next_frame(&mut self) -> Result<Option<SourceFrame<'_>>, crate::Error> {
+        let (dimx, dimy) = self.capturer.geometry();
         self.capturer.capture_store_frame().map_err(Error::Capture)?;
         let img = self.capturer.get_stored_frame().expect("Frame was stored earlier");
-        for (y, (row, prev_row)) in  img.chunks_exact(dimx as usize).zip(self.previous.chunks_exact(dimx as usize)).enumerate() {
-            for (x, (px, prev_px)) in row.iter().zip(prev_row).enumerate() {
-                if px == prev_px {
-                    continue;
-                }
-                let pos = Pos::new(x as u32, y as u32);
-                let col = px.into();
-                Msg::SetPx(pos, col).encode(buf)?;
-            }
-        }
-        Ok(())
+        self.pixels.clear();
+        self.pixels.extend(img.iter().flat_map(|px| [px.r, px.g, px.b, u8::MAX]));
+        Ok(Some(SourceFrame::rgba(dimx, dimy, &self.pixels, Duration::ZERO)))
     }
 }
 
@@ -91,4 +47,4 @@ impl From<&captrs::Bgr8> for Rgba {
             a: None,
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,184 @@
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use thiserror::Error;
+use crate::{Msg, Pos, Rgba, Size};
+use crate::source::Alpha;
+
+/// Minimal Pixelflut server on localhost for testing, with an inspectable framebuffer.
+/// Supports `PX` in both [`Encoding`](crate::Encoding)s, `SIZE`, `HELP` and `OFFSET`.
+/// Needs **features = ["server"]**.
+///
+/// Pixels with an alpha value are blended onto the framebuffer. Commands outside the
+/// framebuffer are ignored without a response and invalid commands close the
+/// connection. The server stops when dropped.
+pub struct Server {
+    addr: SocketAddr,
+    shared: Arc<Shared>,
+    accept: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    size: Size,
+    pixels: Mutex<Vec<Rgba>>,
+    /// Clones of all open connections, to close them on demand.
+    connections: Mutex<Vec<TcpStream>>,
+    stopped: AtomicBool,
+}
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Unable to start server")]
+    Start(#[source] io::Error),
+}
+
+const HELP: &[u8] = b"Commands:\n\
+    PX x y rrggbb|rrggbbaa|ww sets a pixel, PX x y gets its color\n\
+    PB followed by u16 x and y and RGBA bytes sets a pixel\n\
+    SIZE returns the size of the canvas\n\
+    OFFSET x y moves the following commands\n\
+    HELP prints this text\n";
+
+impl Server {
+    /// Starts a server with an opaque black framebuffer of `size` on a free port.
+    pub fn start(size: Size) -> Result<Self, Error> {
+        let listener = TcpListener::bind("127.0.0.1:0").map_err(Error::Start)?;
+        let addr = listener.local_addr().map_err(Error::Start)?;
+        let shared = Arc::new(Shared {
+            size,
+            pixels: Mutex::new(vec![Rgba::new(0, 0, 0, None); size.x as usize * size.y as usize]),
+            connections: Mutex::new(vec![]),
+            stopped: AtomicBool::new(false),
+        });
+        let accept_shared = shared.clone();
+        let accept = thread::Builder::new()
+            .name("barrel-server".to_string())
+            .spawn(move || accept_loop(listener, accept_shared))
+            .map_err(Error::Start)?;
+        Ok(Self { addr, shared, accept: Some(accept) })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn size(&self) -> Size {
+        self.shared.size
+    }
+
+    /// `None` if `pos` is outside the framebuffer.
+    pub fn get(&self, pos: Pos) -> Option<Rgba> {
+        let idx = self.shared.index(pos)?;
+        Some(self.shared.pixels.lock().unwrap()[idx])
+    }
+
+    /// Sets a pixel directly, e.g. to simulate other clients.
+    pub fn set(&self, pos: Pos, col: Rgba) {
+        if let Some(idx) = self.shared.index(pos) {
+            self.shared.pixels.lock().unwrap()[idx] = col;
+        }
+    }
+
+    /// Copy of the framebuffer in row-major order.
+    pub fn pixels(&self) -> Vec<Rgba> {
+        self.shared.pixels.lock().unwrap().clone()
+    }
+
+    /// Closes all open connections, like a server kicking its clients.
+    pub fn disconnect_all(&self) {
+        for stream in self.shared.connections.lock().unwrap().drain(..) {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.shared.stopped.store(true, Ordering::SeqCst);
+        // Wakes up the accept loop
+        let _ = TcpStream::connect(self.addr);
+        if let Some(accept) = self.accept.take() {
+            let _ = accept.join();
+        }
+        self.disconnect_all();
+    }
+}
+
+impl Shared {
+    fn index(&self, pos: Pos) -> Option<usize> {
+        (pos.x < self.size.x && pos.y < self.size.y)
+            .then(|| pos.y as usize * self.size.x as usize + pos.x as usize)
+    }
+
+    /// Applies `msg` and appends its response, if any, to `out`.
+    fn apply(&self, msg: Msg, offset: &mut Pos, pixels: &mut [Rgba], out: &mut Vec<u8>) -> io::Result<()> {
+        let abs = |pos: Pos| Some(Pos::new(pos.x.checked_add(offset.x)?, pos.y.checked_add(offset.y)?));
+        match msg {
+            Msg::SetPx(pos, col) => {
+                if let Some(idx) = abs(pos).and_then(|pos| self.index(pos)) {
+                    pixels[idx] = Alpha::Blend(pixels[idx]).apply(col);
+                }
+            }
+            Msg::GetPx(pos) => {
+                if let Some(idx) = abs(pos).and_then(|pos| self.index(pos)) {
+                    Msg::SetPx(pos, pixels[idx]).encode(out)?;
+                }
+            }
+            Msg::Offset(pos) => *offset = pos,
+            Msg::GetSize => writeln!(out, "SIZE {} {}", self.size.x, self.size.y)?,
+            Msg::Help => out.extend_from_slice(HELP),
+        }
+        Ok(())
+    }
+}
+
+fn accept_loop(listener: TcpListener, shared: Arc<Shared>) {
+    for stream in listener.incoming() {
+        if shared.stopped.load(Ordering::SeqCst) {
+            return;
+        }
+        let Ok(stream) = stream else {
+            continue;
+        };
+        if let Ok(clone) = stream.try_clone() {
+            shared.connections.lock().unwrap().push(clone);
+        }
+        let shared = shared.clone();
+        thread::spawn(move || {
+            let _ = handle_connection(stream, &shared);
+        });
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, shared: &Shared) -> io::Result<()> {
+    let mut buf = vec![];
+    let mut chunk = vec![0; 64 * 1024];
+    let mut out = vec![];
+    let mut offset = Pos::default();
+    loop {
+        let len = stream.read(&mut chunk)?;
+        if len == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..len]);
+        let mut start = 0;
+        {
+            let mut pixels = shared.pixels.lock().unwrap();
+            loop {
+                let decoded = Msg::decode(&buf[start..])
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                let Some((msg, len)) = decoded else {
+                    break;
+                };
+                start += len;
+                shared.apply(msg, &mut offset, &mut pixels, &mut out)?;
+            }
+        }
+        buf.drain(..start);
+        // Responses are written without holding the lock, as the client may be slow
+        stream.write_all(&out)?;
+        out.clear();
+    }
+}
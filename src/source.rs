@@ -0,0 +1,298 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::{Encoder, Error, Msg, Pos, Rgba};
+
+/// A source of frames which can be sent via [`Client::stream`](crate::Client::stream).
+pub trait FrameSource {
+    /// The next frame or `None` if the source is exhausted.
+    fn next_frame(&mut self) -> Result<Option<SourceFrame<'_>>, Error>;
+}
+
+/// A single frame of a [`FrameSource`] which should be shown for `delay`.
+pub struct SourceFrame<'a> {
+    pub(crate) kind: FrameKind<'a>,
+    delay: Duration,
+}
+
+pub(crate) enum FrameKind<'a> {
+    Rgba { width: u32, height: u32, pixels: &'a [u8] },
+    #[cfg(feature = "image")]
+    Encoded { encoder: &'a Encoder, frame: &'a Frame },
+}
+
+/// Options for sending frames via [`Client::stream`](crate::Client::stream). Pre-encoded
+/// frames, e.g. of a `GifWriter`, already had these options applied when loading.
+#[derive(Clone, Debug)]
+pub struct StreamOptions {
+    offset: Pos,
+    alpha_threshold: u8,
+    alpha: Alpha,
+    frame_mode: FrameMode,
+}
+
+/// How pixels which are not fully opaque are sent.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Alpha {
+    /// Send the alpha value and let the server blend. Fully opaque pixels are sent
+    /// without alpha value.
+    #[default]
+    Send,
+    /// Blend onto the given background color and send opaque pixels.
+    Blend(Rgba),
+    /// Drop the alpha value and send opaque pixels.
+    Ignore,
+}
+
+/// Which pixels of consecutive frames are sent.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum FrameMode {
+    /// Send every frame completely.
+    #[default]
+    Full,
+    /// Only send pixels which changed since the previous frame. The first frame and,
+    /// if set, every `keyframe_interval`-th frame are keyframes which are sent completely
+    /// to repair pixels overwritten by others.
+    Delta { keyframe_interval: Option<usize> },
+}
+
+/// A frame encoded into commands. Clones share the data, e.g. to hand a frame to the
+/// threads of a [`ClientPool`](crate::pool::ClientPool).
+#[derive(Clone, Debug)]
+pub(crate) struct Frame {
+    /// Pre-encoded commands of all rows.
+    data: Arc<Vec<u8>>,
+    /// Start of every row in `data`. Rows are encoded as independent segments,
+    /// see [`Encoder::start_segment`].
+    rows: Arc<Vec<usize>>,
+}
+
+/// Encodes RGBA frames according to [`StreamOptions`], remembering the previous frame
+/// for [`FrameMode::Delta`].
+pub(crate) struct FrameEncoder {
+    options: StreamOptions,
+    prev: Vec<u8>,
+    prev_dim: (u32, u32),
+    frame_idx: usize,
+}
+
+/// Paces consecutive frames according to their delay, independent of how long
+/// sending them took.
+pub(crate) struct Pacer {
+    deadline: Instant,
+}
+
+impl<'a> SourceFrame<'a> {
+    /// A frame of `width * height` row-major RGBA pixels.
+    ///
+    /// # Panics
+    /// If `pixels` doesn't contain exactly `4 * width * height` bytes.
+    pub fn rgba(width: u32, height: u32, pixels: &'a [u8], delay: Duration) -> Self {
+        assert_eq!(pixels.len(), 4 * width as usize * height as usize, "frame size mismatch");
+        Self { kind: FrameKind::Rgba { width, height, pixels }, delay }
+    }
+
+    #[cfg(feature = "image")]
+    pub(crate) fn encoded(encoder: &'a Encoder, frame: &'a Frame, delay: Duration) -> Self {
+        Self { kind: FrameKind::Encoded { encoder, frame }, delay }
+    }
+
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+}
+
+impl StreamOptions {
+    /// Position of the top left corner of the frames on the board.
+    pub fn offset(mut self, offset: Pos) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Pixels with an alpha value below the threshold are not sent. Defaults to `1`,
+    /// i.e. only fully transparent pixels are skipped.
+    pub fn alpha_threshold(mut self, threshold: u8) -> Self {
+        self.alpha_threshold = threshold;
+        self
+    }
+
+    /// How pixels which are sent but not fully opaque are handled.
+    pub fn alpha(mut self, alpha: Alpha) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    pub fn frame_mode(mut self, frame_mode: FrameMode) -> Self {
+        self.frame_mode = frame_mode;
+        self
+    }
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            offset: Pos::default(),
+            alpha_threshold: 1,
+            alpha: Alpha::default(),
+            frame_mode: FrameMode::default(),
+        }
+    }
+}
+
+impl FrameMode {
+    fn is_keyframe(self, idx: usize) -> bool {
+        match self {
+            FrameMode::Full => true,
+            FrameMode::Delta { keyframe_interval } => {
+                idx == 0 || keyframe_interval.is_some_and(|interval| idx.is_multiple_of(interval))
+            }
+        }
+    }
+}
+
+impl Alpha {
+    pub(crate) fn apply(self, col: Rgba) -> Rgba {
+        match (self, col.a) {
+            (_, None) => col,
+            (_, Some(u8::MAX)) | (Alpha::Ignore, Some(_)) => Rgba { a: None, ..col },
+            (Alpha::Send, Some(_)) => col,
+            (Alpha::Blend(bg), Some(a)) => {
+                let blend = |fg: u8, bg: u8| {
+                    ((fg as u32 * a as u32 + bg as u32 * (255 - a as u32) + 127) / 255) as u8
+                };
+                Rgba::new(blend(col.r, bg.r), blend(col.g, bg.g), blend(col.b, bg.b), None)
+            }
+        }
+    }
+}
+
+impl Frame {
+    pub(crate) fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// The independently sendable rows of the frame.
+    pub(crate) fn rows(&self) -> impl Iterator<Item = &[u8]> {
+        let ends = self.rows.iter().skip(1).copied().chain([self.data.len()]);
+        self.rows.iter().zip(ends).map(|(&start, end)| &self.data[start..end])
+    }
+}
+
+/// Encodes the frames of `source` in the format of `encoder` and hands them to `send`,
+/// pacing them according to their delay. Shared by the `stream` methods of all clients.
+pub(crate) fn stream_frames(
+    source: &mut impl FrameSource,
+    options: &StreamOptions,
+    encoder: &Encoder,
+    mut send: impl FnMut(&Frame) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let mut frame_encoder = FrameEncoder::new(options);
+    let mut pacer = Pacer::new();
+    while let Some(frame) = source.next_frame()? {
+        match frame.kind {
+            FrameKind::Rgba { width, height, pixels } => {
+                send(&frame_encoder.encode(encoder, width, height, pixels))?;
+            }
+            #[cfg(feature = "image")]
+            FrameKind::Encoded { encoder: format, frame } => {
+                if !encoder.same_format(format) {
+                    return Err(Error::FormatMismatch);
+                }
+                send(frame)?;
+            }
+        }
+        pacer.wait(frame.delay());
+    }
+    Ok(())
+}
+
+impl Pacer {
+    pub(crate) fn new() -> Self {
+        Self { deadline: Instant::now() }
+    }
+
+    /// Sleeps until `delay` after the previous deadline. If that has already
+    /// passed, the next delay starts now.
+    pub(crate) fn wait(&mut self, delay: Duration) {
+        self.deadline += delay;
+        match self.deadline.checked_duration_since(Instant::now()) {
+            Some(wait) => thread::sleep(wait),
+            None => self.deadline = Instant::now(),
+        }
+    }
+}
+
+impl FrameEncoder {
+    pub(crate) fn new(options: &StreamOptions) -> Self {
+        Self {
+            options: options.clone(),
+            prev: vec![],
+            prev_dim: (0, 0),
+            frame_idx: 0,
+        }
+    }
+
+    /// Encodes `width * height` RGBA `pixels` in the format of `encoder`.
+    pub(crate) fn encode(&mut self, encoder: &Encoder, width: u32, height: u32, pixels: &[u8]) -> Frame {
+        let diff_base = (!self.options.frame_mode.is_keyframe(self.frame_idx) && self.prev_dim == (width, height))
+            .then_some(self.prev.as_slice());
+        let mut encoder = encoder.with_same_format();
+        let mut data = Vec::with_capacity(pixels.len() * 4);
+        let mut rows = Vec::with_capacity(height as usize);
+        let row_len = width as usize * 4;
+        for (y, row) in pixels.chunks_exact(row_len.max(1)).enumerate() {
+            rows.push(data.len());
+            encoder.start_segment();
+            for (x, px) in row.chunks_exact(4).enumerate() {
+                if px[3] < self.options.alpha_threshold {
+                    continue;
+                }
+                if let Some(prev) = diff_base {
+                    let idx = y * row_len + x * 4;
+                    if &prev[idx..idx + 4] == px {
+                        continue;
+                    }
+                }
+                let pos = Pos::new(self.options.offset.x + x as u32, self.options.offset.y + y as u32);
+                let col = self.options.alpha.apply(Rgba::new(px[0], px[1], px[2], Some(px[3])));
+                encoder.encode(&Msg::SetPx(pos, col), &mut data).expect("writing to a Vec is infallible");
+            }
+        }
+        self.prev.clear();
+        self.prev.extend_from_slice(pixels);
+        self.prev_dim = (width, height);
+        self.frame_idx += 1;
+        Frame { data: Arc::new(data), rows: Arc::new(rows) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Encoder, Rgba};
+    use crate::source::{Alpha, FrameEncoder, FrameMode, StreamOptions};
+
+    #[test]
+    fn delta_frames() {
+        let options = StreamOptions::default().frame_mode(FrameMode::Delta { keyframe_interval: None });
+        let mut frame_encoder = FrameEncoder::new(&options);
+        let encoder = Encoder::default();
+        let mut pixels = [0, 0, 0, 255].repeat(4);
+        frame_encoder.encode(&encoder, 2, 2, &pixels);
+        pixels[12..].copy_from_slice(&[255, 0, 0, 255]);
+        let frame = frame_encoder.encode(&encoder, 2, 2, &pixels);
+        assert_eq!(frame.data(), b"PX 1 1 ff0000\n");
+
+        let mode = FrameMode::Delta { keyframe_interval: Some(3) };
+        let keyframes: Vec<_> = (0..7).filter(|&idx| mode.is_keyframe(idx)).collect();
+        assert_eq!(keyframes, [0, 3, 6]);
+    }
+
+    #[test]
+    fn blend_alpha() {
+        let col = Rgba::new(255, 0, 100, Some(128));
+        let blended = Alpha::Blend(Rgba::new(0, 255, 100, None)).apply(col);
+        assert_eq!(blended, Rgba::new(128, 127, 100, None));
+        assert_eq!(Alpha::Ignore.apply(col), Rgba::new(255, 0, 100, None));
+        assert_eq!(Alpha::Send.apply(col), col);
+    }
+}
@@ -0,0 +1,235 @@
+use std::cell::Cell;
+use std::io::{BufReader, BufWriter, Write};
+use std::net::{Shutdown, TcpStream};
+use std::ops::Range;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use crate::{Encoder, Encoding, Error, Msg, Response};
+use crate::codec::ResponseReader;
+
+/// Position of a request in the order of all requests expecting a response sent over
+/// a connection, starting at `0`. Servers answer in request order, so the n-th
+/// response belongs to request `n`.
+pub type RequestId = u64;
+
+type Received = (RequestId, Result<Response, Error>);
+
+/// Sending half of a [`Client`](crate::Client), see [`Client::split`](crate::Client::split).
+pub struct ClientWriter {
+    pub(crate) write: BufWriter<TcpStream>,
+    pub(crate) encoder: Encoder,
+    /// Id of the next request expecting a response.
+    requests: RequestId,
+    /// Ids of [`Msg::Help`] requests, whose free-form answers the reader can't tell
+    /// from unexpected lines otherwise.
+    helps: Sender<RequestId>,
+}
+
+/// Receiving half of a [`Client`](crate::Client). A background thread reads and decodes
+/// responses as soon as they arrive, so the server never blocks on a full send buffer
+/// while the writer is busy.
+pub struct ClientReader {
+    /// Used to stop the background thread on drop.
+    stream: TcpStream,
+    responses: Receiver<Received>,
+    /// Id of the next request whose response wasn't received yet.
+    next: Cell<RequestId>,
+}
+
+impl ClientWriter {
+    pub(crate) fn new(stream: TcpStream, helps: Sender<RequestId>) -> Self {
+        Self { write: BufWriter::new(stream), encoder: Encoder::default(), requests: 0, helps }
+    }
+
+    /// Set the [`Encoding`] used for all subsequently sent pixel commands.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoder.set_encoding(encoding);
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoder.encoding()
+    }
+
+    /// See [`Client::set_tile_offsets`](crate::Client::set_tile_offsets).
+    pub fn set_tile_offsets(&mut self, tile_size: Option<u32>) {
+        self.encoder.set_tile_offsets(tile_size);
+    }
+
+    pub fn encoder(&self) -> &Encoder {
+        &self.encoder
+    }
+
+    /// The [`RequestId`] the next message expecting a response will get.
+    pub fn next_request_id(&self) -> RequestId {
+        self.requests
+    }
+
+    /// Flushes the internal buffer after sending.
+    pub fn send(&mut self, msg: Msg) -> Result<(), Error> {
+        self.send_buffered(msg)?;
+        self.flush()
+    }
+
+    /// Flushes after sending all messages. Returns the ids of the requests expecting a
+    /// response, whose responses arrive at the [`ClientReader`].
+    pub fn send_all(&mut self, msgs: &[Msg]) -> Result<Range<RequestId>, Error> {
+        let start = self.requests;
+        for msg in msgs {
+            self.send_buffered(*msg)?;
+        }
+        self.flush()?;
+        Ok(start..self.requests)
+    }
+
+    /// Does not explicitly flush the buffer after sending.
+    #[inline]
+    pub fn send_buffered(&mut self, msg: Msg) -> Result<(), Error> {
+        if msg == Msg::Help {
+            // Registered before sending, so the reader knows it once the answer arrives.
+            // Only fails if the reader is gone, which then doesn't need it.
+            let _ = self.helps.send(self.requests);
+        }
+        self.encoder.encode(&msg, &mut self.write).map_err(Error::SendCmd)?;
+        if msg.expect_response() {
+            self.requests += 1;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.write.flush().map_err(Error::SendCmd)
+    }
+}
+
+impl Drop for ClientWriter {
+    /// Flushes and signals the end of the requests to the server. Responses to
+    /// requests sent before are still received.
+    fn drop(&mut self) {
+        let _ = self.write.flush();
+        let _ = self.write.get_ref().shutdown(Shutdown::Write);
+    }
+}
+
+impl ClientReader {
+    pub(crate) fn spawn(stream: TcpStream, helps: Receiver<RequestId>) -> Result<Self, Error> {
+        let (tx, responses) = mpsc::channel();
+        let read = stream.try_clone().map_err(Error::Connect)?;
+        thread::Builder::new()
+            .name("barrel-reader".to_string())
+            .spawn(move || read_responses(read, helps, tx))
+            .map_err(Error::Connect)?;
+        Ok(Self { stream, responses, next: Cell::new(0) })
+    }
+
+    /// Blocks until the next response arrives and returns it with the id of its request.
+    /// Once the connection is closed and all responses were received, the next request
+    /// fails with [`Error::MissingData`].
+    pub fn recv(&self) -> (RequestId, Result<Response, Error>) {
+        match self.responses.recv() {
+            Ok(received) => self.received(received),
+            Err(_) => (self.next.get(), Err(Error::MissingData)),
+        }
+    }
+
+    /// The next response if one already arrived, see [`ClientReader::recv`].
+    pub fn try_recv(&self) -> Option<(RequestId, Result<Response, Error>)> {
+        match self.responses.try_recv() {
+            Ok(received) => Some(self.received(received)),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some((self.next.get(), Err(Error::MissingData))),
+        }
+    }
+
+    fn received(&self, (id, resp): Received) -> Received {
+        self.next.set(id + 1);
+        (id, resp)
+    }
+}
+
+impl Drop for ClientReader {
+    fn drop(&mut self) {
+        // Wakes up the background thread, which then exits
+        let _ = self.stream.shutdown(Shutdown::Read);
+    }
+}
+
+/// Runs on the background thread of a [`ClientReader`] until the connection is closed
+/// or the reader is dropped.
+fn read_responses(stream: TcpStream, helps: Receiver<RequestId>, tx: Sender<Received>) {
+    let mut read = ResponseReader::new(BufReader::new(stream));
+    let mut next_help = None;
+    for id in 0.. {
+        // Help requests are registered before they are sent, so they are known once
+        // their answer starts to arrive
+        let resp = read.wait().and_then(|()| {
+            if next_help.is_none_or(|help| help < id) {
+                next_help = helps.try_iter().find(|&help| help >= id);
+            }
+            if next_help == Some(id) { read.next_help() } else { read.next_response() }
+        });
+        let Some(resp) = resp.transpose() else {
+            return;
+        };
+        let failed = matches!(resp, Err(Error::Receive(_)));
+        if tx.send((id, resp)).is_err() || failed {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use crate::{Client, Error, Msg, Pos, Response, Rgba, Size};
+
+    #[test]
+    fn responses_keep_request_order() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut write = stream.try_clone().unwrap();
+            let sizes = BufReader::new(stream).lines().filter(|line| line.as_ref().unwrap() == "SIZE");
+            for (idx, _) in sizes.enumerate() {
+                writeln!(write, "SIZE {idx} 1").unwrap();
+            }
+        });
+
+        let (mut writer, reader) = Client::connect(addr).unwrap().split();
+        writer.send(Msg::SetPx(Pos::new(0, 0), Rgba::red())).unwrap();
+        let ids = writer.send_all(&[Msg::GetSize, Msg::SetPx(Pos::new(1, 0), Rgba::red()), Msg::GetSize]).unwrap();
+        assert_eq!(ids, 0..2);
+        drop(writer);
+        for expected in 0..2 {
+            let (id, resp) = reader.recv();
+            assert_eq!(id, expected);
+            assert!(matches!(resp, Ok(Response::Size(size)) if size == Size::new(expected as u32, 1)));
+        }
+        server.join().unwrap();
+        assert!(matches!(reader.recv(), (2, Err(Error::MissingData))));
+    }
+
+    #[test]
+    fn errors_keep_their_request_id() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut write = stream.try_clone().unwrap();
+            // Reads all requests, so closing doesn't reset the connection
+            assert_eq!(BufReader::new(stream).lines().count(), 3);
+            write.write_all(b"SIZE 1 1\nSIZE -1 1\nSIZE 3 1\n").unwrap();
+        });
+
+        let (mut writer, reader) = Client::connect(addr).unwrap().split();
+        writer.send_all(&[Msg::GetSize; 3]).unwrap();
+        drop(writer);
+        server.join().unwrap();
+        assert!(matches!(reader.recv(), (0, Ok(_))));
+        assert!(matches!(reader.recv(), (1, Err(Error::Parse(_)))));
+        assert!(matches!(reader.recv(), (2, Ok(_))));
+        assert!(matches!(reader.recv(), (3, Err(Error::MissingData))));
+    }
+}
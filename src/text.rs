@@ -0,0 +1,333 @@
+use crate::{Pos, Rgba, Size};
+use crate::draw::DrawTarget;
+
+/// Width of a glyph of the built-in font, without spacing.
+const GLYPH_WIDTH: u32 = 5;
+/// Height of a glyph of the built-in font, without spacing.
+const GLYPH_HEIGHT: u32 = 7;
+
+/// Options for drawing text via [`text`].
+#[derive(Clone, Debug)]
+pub struct TextStyle {
+    font: Font,
+    size: u32,
+    color: Rgba,
+    background: Option<Rgba>,
+    align: Align,
+}
+
+/// Font used to render text.
+#[derive(Clone, Debug, Default)]
+pub enum Font {
+    /// Built-in 5x7 bitmap font covering printable ASCII. Other characters are drawn
+    /// as `?`.
+    #[default]
+    Builtin,
+    /// TrueType or OpenType font. Needs **features = ["ttf"]**.
+    #[cfg(feature = "ttf")]
+    Ttf(rusttype::Font<'static>),
+}
+
+/// Horizontal alignment of every line relative to the position passed to [`text`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+/// Rendered line of text. `true` pixels are covered by a glyph.
+struct Mask {
+    width: u32,
+    pixels: Vec<bool>,
+}
+
+impl TextStyle {
+    pub fn font(mut self, font: Font) -> Self {
+        self.font = font;
+        self
+    }
+
+    /// Height of a line in pixels. The built-in font is scaled by integer factors, so
+    /// its glyphs are `7 * (size / 8)` pixels high. Smaller sizes are raised to 8 for it.
+    pub fn size(mut self, size: u32) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn color(mut self, color: Rgba) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Fill the bounding box of every line with `background`. By default only the
+    /// glyphs are drawn.
+    pub fn background(mut self, background: Option<Rgba>) -> Self {
+        self.background = background;
+        self
+    }
+
+    pub fn align(mut self, align: Align) -> Self {
+        self.align = align;
+        self
+    }
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            font: Font::default(),
+            size: GLYPH_HEIGHT + 1,
+            color: Rgba::new(255, 255, 255, None),
+            background: None,
+            align: Align::default(),
+        }
+    }
+}
+
+impl Font {
+    /// Loads a TrueType or OpenType font, `None` if the data is invalid. Needs
+    /// **features = ["ttf"]**.
+    #[cfg(feature = "ttf")]
+    pub fn ttf(data: Vec<u8>) -> Option<Self> {
+        rusttype::Font::try_from_vec(data).map(Font::Ttf)
+    }
+
+    /// Height of the lines drawn for the requested `size`.
+    fn line_height(&self, size: u32) -> u32 {
+        match self {
+            Font::Builtin => size.max(GLYPH_HEIGHT + 1),
+            #[cfg(feature = "ttf")]
+            Font::Ttf(_) => size,
+        }
+    }
+
+    fn render(&self, line: &str, size: u32) -> Mask {
+        match self {
+            Font::Builtin => render_builtin(line, size),
+            #[cfg(feature = "ttf")]
+            Font::Ttf(font) => render_ttf(font, line, size),
+        }
+    }
+}
+
+/// Draws `text` with its first line at `pos`. Lines are separated by `\n` and are
+/// [`TextStyle::size`] pixels apart. Use a [`MsgBuffer`](crate::draw::MsgBuffer)
+/// to get [`Msg::SetPx`](crate::Msg::SetPx) commands.
+pub fn text(target: &mut impl DrawTarget, pos: Pos, text: &str, style: &TextStyle) {
+    let line_height = style.font.line_height(style.size);
+    for (idx, line) in text.lines().enumerate() {
+        let mask = style.font.render(line, line_height);
+        let x = match style.align {
+            Align::Left => pos.x as i64,
+            Align::Center => pos.x as i64 - mask.width as i64 / 2,
+            Align::Right => pos.x as i64 - mask.width as i64,
+        };
+        let y = pos.y as i64 + idx as i64 * line_height as i64;
+        for (i, &covered) in mask.pixels.iter().enumerate() {
+            let col = if covered { Some(style.color) } else { style.background };
+            let (px, py) = (x + (i as u32 % mask.width) as i64, y + (i as u32 / mask.width) as i64);
+            if let (Some(col), Ok(px), Ok(py)) = (col, u32::try_from(px), u32::try_from(py)) {
+                target.set(Pos::new(px, py), col);
+            }
+        }
+    }
+}
+
+/// Size of the bounding box of `text` drawn with `style`.
+pub fn text_size(text: &str, style: &TextStyle) -> Size {
+    let line_height = style.font.line_height(style.size);
+    let mut size = Size::default();
+    for line in text.lines() {
+        let mask = style.font.render(line, line_height);
+        size.x = size.x.max(mask.width);
+        size.y += line_height;
+    }
+    size
+}
+
+fn render_builtin(line: &str, size: u32) -> Mask {
+    let scale = (size / (GLYPH_HEIGHT + 1)).max(1);
+    let advance = GLYPH_WIDTH + 1;
+    let width = line.chars().count() as u32 * advance * scale;
+    let mut pixels = vec![false; width as usize * size as usize];
+    for (idx, c) in line.chars().enumerate() {
+        let glyph = glyph(c);
+        for (col, bits) in glyph.iter().enumerate() {
+            for row in 0..GLYPH_HEIGHT {
+                if bits & (1 << row) == 0 {
+                    continue;
+                }
+                let x = (idx as u32 * advance + col as u32) * scale;
+                for y in (row * scale..(row + 1) * scale).take_while(|&y| y < size) {
+                    let start = (y * width + x) as usize;
+                    pixels[start..start + scale as usize].fill(true);
+                }
+            }
+        }
+    }
+    Mask { width, pixels }
+}
+
+#[cfg(feature = "ttf")]
+fn render_ttf(font: &rusttype::Font<'static>, line: &str, size: u32) -> Mask {
+    use image024::{GrayImage, Luma};
+    let scale = rusttype::Scale::uniform(size as f32);
+    let (width, _) = imageproc::drawing::text_size(scale, font, line);
+    let width = width.max(0) as u32;
+    if width == 0 || size == 0 {
+        return Mask { width: 0, pixels: vec![] };
+    }
+    let mut coverage = GrayImage::new(width, size);
+    imageproc::drawing::draw_text_mut(&mut coverage, Luma([u8::MAX]), 0, 0, scale, font, line);
+    // Pixelflut has no anti-aliasing, so glyphs are thresholded at half coverage.
+    let pixels = coverage.pixels().map(|px| px.0[0] >= 128).collect();
+    Mask { width, pixels }
+}
+
+/// Columns of the built-in glyph for `c`, least significant bit at the top.
+fn glyph(c: char) -> &'static [u8; GLYPH_WIDTH as usize] {
+    let idx = match c {
+        ' '..='~' => c as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    };
+    &FONT[idx]
+}
+
+/// Classic 5x7 font for the printable ASCII range `' '..='~'`.
+const FONT: [[u8; GLYPH_WIDTH as usize]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5f, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7f, 0x14, 0x7f, 0x14], // #
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1c, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1c, 0x00], // )
+    [0x08, 0x2a, 0x1c, 0x2a, 0x08], // *
+    [0x08, 0x08, 0x3e, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3e, 0x51, 0x49, 0x45, 0x3e], // 0
+    [0x00, 0x42, 0x7f, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4b, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7f, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3c, 0x4a, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1e], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3e], // @
+    [0x7e, 0x11, 0x11, 0x11, 0x7e], // A
+    [0x7f, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3e, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7f, 0x41, 0x41, 0x22, 0x1c], // D
+    [0x7f, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7f, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3e, 0x41, 0x49, 0x49, 0x7a], // G
+    [0x7f, 0x08, 0x08, 0x08, 0x7f], // H
+    [0x00, 0x41, 0x7f, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3f, 0x01], // J
+    [0x7f, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7f, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7f, 0x02, 0x0c, 0x02, 0x7f], // M
+    [0x7f, 0x04, 0x08, 0x10, 0x7f], // N
+    [0x3e, 0x41, 0x41, 0x41, 0x3e], // O
+    [0x7f, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3e, 0x41, 0x51, 0x21, 0x5e], // Q
+    [0x7f, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7f, 0x01, 0x01], // T
+    [0x3f, 0x40, 0x40, 0x40, 0x3f], // U
+    [0x1f, 0x20, 0x40, 0x20, 0x1f], // V
+    [0x3f, 0x40, 0x38, 0x40, 0x3f], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x07, 0x08, 0x70, 0x08, 0x07], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7f, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x7f, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x01, 0x02, 0x04, 0x00], // `
+    [0x20, 0x54, 0x54, 0x54, 0x78], // a
+    [0x7f, 0x48, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x20], // c
+    [0x38, 0x44, 0x44, 0x48, 0x7f], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x08, 0x7e, 0x09, 0x01, 0x02], // f
+    [0x0c, 0x52, 0x52, 0x52, 0x3e], // g
+    [0x7f, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7d, 0x40, 0x00], // i
+    [0x20, 0x40, 0x44, 0x3d, 0x00], // j
+    [0x7f, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7f, 0x40, 0x00], // l
+    [0x7c, 0x04, 0x18, 0x04, 0x78], // m
+    [0x7c, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0x7c, 0x14, 0x14, 0x14, 0x08], // p
+    [0x08, 0x14, 0x14, 0x18, 0x7c], // q
+    [0x7c, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x20], // s
+    [0x04, 0x3f, 0x44, 0x40, 0x20], // t
+    [0x3c, 0x40, 0x40, 0x20, 0x7c], // u
+    [0x1c, 0x20, 0x40, 0x20, 0x1c], // v
+    [0x3c, 0x40, 0x30, 0x40, 0x3c], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x0c, 0x50, 0x50, 0x50, 0x3c], // y
+    [0x44, 0x64, 0x54, 0x4c, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x7f, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x08, 0x04, 0x08, 0x10, 0x08], // ~
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::{Msg, Pos, Rgba, Size};
+    use crate::draw::MsgBuffer;
+    use crate::text::{self, Align, TextStyle};
+
+    #[test]
+    fn builtin_font() {
+        let style = TextStyle::default().size(16).align(Align::Right);
+        assert_eq!(text::text_size("Hi!\nfoo", &style), Size::new(36, 32));
+
+        let mut buf = MsgBuffer::new(Size::new(100, 100));
+        text::text(&mut buf, Pos::new(12, 0), "I", &style);
+        let mut positions: Vec<_> = buf.msgs().iter().map(|msg| match msg {
+            Msg::SetPx(pos, col) if *col == Rgba::new(255, 255, 255, None) => (pos.x, pos.y),
+            _ => unreachable!(),
+        }).collect();
+        positions.sort_unstable();
+        // The stem of the `I` is two pixels wide and 14 high, serifs add 2 * 2 * 4
+        assert_eq!(positions.len(), 2 * 14 + 16);
+        assert_eq!(positions.first(), Some(&(2, 0)));
+    }
+
+    #[test]
+    fn builtin_font_minimum_size() {
+        let count = |size| {
+            let mut buf = MsgBuffer::new(Size::new(100, 100));
+            text::text(&mut buf, Pos::new(0, 0), "ab\ncd", &TextStyle::default().size(size));
+            buf.msgs().len()
+        };
+        for size in [0, 3] {
+            let style = TextStyle::default().size(size);
+            assert_eq!(text::text_size("ab\ncd", &style), Size::new(12, 16));
+            assert_eq!(count(size), count(8));
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use std::process::{Command, Output};
+use barrel::server::Server;
+use barrel::{Pos, Rgba, Size};
+
+fn barrel(server: &Server, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_barrel"))
+        .arg("--host")
+        .arg(server.addr().to_string())
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn size() {
+    let server = Server::start(Size::new(64, 32)).unwrap();
+    let output = barrel(&server, &["size"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "64x32\n");
+}
+
+#[test]
+fn fill() {
+    let server = Server::start(Size::new(16, 16)).unwrap();
+    let output = barrel(&server, &["fill", "ff0000", "--offset", "4,4", "--size", "2x2", "--connections", "2", "--encoding", "binary"]);
+    assert!(output.status.success());
+    assert_eq!(server.get(Pos::new(5, 5)), Some(Rgba::red()));
+    assert_eq!(server.get(Pos::new(6, 5)), Some(Rgba::new(0, 0, 0, None)));
+}
+
+#[test]
+fn text() {
+    let server = Server::start(Size::new(32, 16)).unwrap();
+    let output = barrel(&server, &["text", "Hi", "--color", "00ff00"]);
+    assert!(output.status.success());
+    assert!(server.pixels().contains(&Rgba::green()));
+}
+
+#[test]
+fn invalid_arguments_fail() {
+    let server = Server::start(Size::new(4, 4)).unwrap();
+    assert!(!barrel(&server, &["fill", "red"]).status.success());
+    assert!(!barrel(&server, &["fill", "+f"]).status.success());
+    assert!(!barrel(&server, &["fill", "ff0000", "--offset", "4"]).status.success());
+}
@@ -0,0 +1,267 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use barrel::canvas::Canvas;
+use barrel::guard::{Guard, GuardOptions};
+use barrel::pool::{ClientPool, Sharding};
+use barrel::reconnect::{Backoff, ReconnectEvent, ReconnectingClient};
+use barrel::server::Server;
+use barrel::source::{FrameMode, FrameSource, SourceFrame, StreamOptions};
+use barrel::{Client, Encoding, Error, Msg, Pos, Rect, Response, Rgba, Size};
+
+fn start(size: Size) -> (Server, Client) {
+    let server = Server::start(size).unwrap();
+    let client = Client::connect(server.addr()).unwrap();
+    (server, client)
+}
+
+#[test]
+fn size_and_help() {
+    let (_server, mut client) = start(Size::new(64, 32));
+    assert_eq!(client.get_size().unwrap(), Size::new(64, 32));
+    // The help text spans multiple lines, none of which is mistaken for another response
+    let responses = client.send_all(&[Msg::Help, Msg::GetSize, Msg::Help]).unwrap();
+    assert!(matches!(&responses[0], Response::Help(help) if help.lines().count() == 6));
+    assert_eq!(responses[1], Response::Size(Size::new(64, 32)));
+    assert_eq!(responses[2], responses[0]);
+    assert_eq!(client.get_size().unwrap(), Size::new(64, 32));
+}
+
+#[test]
+fn set_and_get_pixels() {
+    for (encoding, tile_size) in [(Encoding::Text, None), (Encoding::Binary, None), (Encoding::Text, Some(16))] {
+        let (server, mut client) = start(Size::new(64, 64));
+        client.set_encoding(encoding);
+        client.set_tile_offsets(tile_size);
+        let col = Rgba::new(1, 2, 3, None);
+        let responses = client.send_all(&[
+            Msg::SetPx(Pos::new(40, 50), col),
+            Msg::SetPx(Pos::new(100, 0), col),
+            Msg::GetPx(Pos::new(40, 50)),
+        ]).unwrap();
+        assert_eq!(responses, [Response::Px(Pos::new(40, 50), col)]);
+        assert_eq!(server.get(Pos::new(40, 50)), Some(col));
+    }
+}
+
+#[test]
+fn alpha_is_blended() {
+    let (server, mut client) = start(Size::new(4, 4));
+    client.send(Msg::SetPx(Pos::new(1, 1), Rgba::new(255, 0, 0, Some(128)))).unwrap();
+    client.get_size().unwrap();
+    assert_eq!(server.get(Pos::new(1, 1)), Some(Rgba::new(128, 0, 0, None)));
+}
+
+#[test]
+fn canvas_flush() {
+    let (server, mut client) = start(Size::new(8, 8));
+    let mut canvas = Canvas::new(Size::new(8, 8));
+    canvas.fill_rect(Rect::new(Pos::new(2, 2), Size::new(3, 3)), Rgba::green());
+    canvas.flush(&mut client).unwrap();
+    client.get_size().unwrap();
+    assert_eq!(server.get(Pos::new(4, 4)), Some(Rgba::green()));
+    assert_eq!(server.get(Pos::new(5, 4)), Some(Rgba::new(0, 0, 0, None)));
+}
+
+#[test]
+fn pool_shards_pixels() {
+    let server = Server::start(Size::new(16, 16)).unwrap();
+    let mut pool = ClientPool::connect(server.addr(), 3).unwrap();
+    pool.set_sharding(Sharding::Rows);
+    pool.set_threaded(true);
+    let msgs: Vec<_> = (0..16 * 16).map(|idx| Msg::SetPx(Pos::new(idx % 16, idx / 16), Rgba::blue())).collect();
+    pool.send_all(&msgs).unwrap();
+    // Connections are processed independently, so wait for all of them
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !server.pixels().iter().all(|&col| col == Rgba::blue()) {
+        assert!(Instant::now() < deadline, "not all pixels were set");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Frames of a 2x2 image.
+struct Frames {
+    frames: Vec<Vec<u8>>,
+    next: usize,
+}
+
+impl FrameSource for Frames {
+    fn next_frame(&mut self) -> Result<Option<SourceFrame<'_>>, Error> {
+        let Some(pixels) = self.frames.get(self.next) else {
+            return Ok(None);
+        };
+        self.next += 1;
+        Ok(Some(SourceFrame::rgba(2, 2, pixels, Duration::ZERO)))
+    }
+}
+
+#[test]
+fn stream_delta_frames() {
+    let (server, mut client) = start(Size::new(8, 8));
+    let first = [255, 0, 0, 255].repeat(4);
+    let mut second = first.clone();
+    second[..4].copy_from_slice(&[0, 0, 255, 255]);
+    let mut source = Frames { frames: vec![first, second], next: 0 };
+    let options = StreamOptions::default()
+        .offset(Pos::new(3, 3))
+        .frame_mode(FrameMode::Delta { keyframe_interval: None });
+    client.stream(&mut source, &options).unwrap();
+    client.get_size().unwrap();
+    assert_eq!(server.get(Pos::new(3, 3)), Some(Rgba::blue()));
+    assert_eq!(server.get(Pos::new(4, 4)), Some(Rgba::red()));
+}
+
+#[test]
+fn threaded_pool_streams_frames() {
+    let server = Server::start(Size::new(8, 8)).unwrap();
+    let mut pool = ClientPool::connect(server.addr(), 2).unwrap();
+    pool.set_threaded(true);
+    let mut source = Frames { frames: vec![[0, 255, 0, 255].repeat(4); 3], next: 0 };
+    pool.stream(&mut source, &StreamOptions::default().offset(Pos::new(1, 1))).unwrap();
+    // Workers hand back their connections
+    pool.set_threaded(false);
+    assert_eq!(pool.get_size().unwrap(), Size::new(8, 8));
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while server.get(Pos::new(2, 2)) != Some(Rgba::green()) || server.get(Pos::new(1, 1)) != Some(Rgba::green()) {
+        assert!(Instant::now() < deadline, "not all rows were set");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn guard_repairs_overwritten_pixels() {
+    let (server, mut client) = start(Size::new(16, 16));
+    for idx in 0..16 * 16 {
+        server.set(Pos::new(idx % 16, idx / 16), Rgba::green());
+    }
+    let block: Vec<_> = (0..16).map(|idx| Pos::new(4 + idx % 4, 10 + idx / 4)).collect();
+    for &pos in &block {
+        server.set(pos, Rgba::red());
+    }
+    // Repainting everything in turn would only reach the block after 10 rounds
+    let pixels = [0, 255, 0, 255].repeat(16 * 16);
+    let options = GuardOptions::default().budget(64).read_share(0.75);
+    let mut guard = Guard::new(Pos::new(0, 0), 16, 16, &pixels, options);
+    let mut damaged = 0;
+    for _ in 0..6 {
+        damaged += guard.round(&mut client).unwrap().damaged;
+    }
+    client.get_size().unwrap();
+    assert!(damaged > 0);
+    assert!(block.iter().all(|&pos| server.get(pos) == Some(Rgba::green())));
+}
+
+#[test]
+fn guard_skips_pixels_outside_of_canvas() {
+    let (server, mut client) = start(Size::new(12, 12));
+    let pixels = [255, 0, 0, 255].repeat(8 * 8);
+    let options = GuardOptions::default().budget(32).read_share(0.5);
+    let mut guard = Guard::new(Pos::new(8, 8), 8, 8, &pixels, options);
+    for _ in 0..2 {
+        let stats = guard.round(&mut client).unwrap();
+        assert_eq!((stats.painted, stats.sampled), (16, 16));
+    }
+    client.get_size().unwrap();
+    assert!((8..12).all(|y| (8..12).all(|x| server.get(Pos::new(x, y)) == Some(Rgba::red()))));
+}
+
+#[test]
+fn closed_connection_is_reported() {
+    let (server, mut client) = start(Size::new(4, 4));
+    client.get_size().unwrap();
+    server.disconnect_all();
+    assert!(client.get_size().is_err());
+}
+
+#[test]
+fn reconnecting_client_resumes_after_kick() {
+    let server = Server::start(Size::new(8, 8)).unwrap();
+    let events = Arc::new(Mutex::new(vec![]));
+    let recorded = events.clone();
+    let backoff = Backoff { initial: Duration::from_millis(1), max_retries: Some(10), ..Backoff::default() };
+    let mut client = ReconnectingClient::connect(server.addr()).unwrap()
+        .with_backoff(backoff)
+        .on_reconnect(move |event| recorded.lock().unwrap().push(match event {
+            ReconnectEvent::Disconnected(_) => "disconnected",
+            ReconnectEvent::Retry { .. } => "retry",
+            ReconnectEvent::Reconnected { .. } => "reconnected",
+            ReconnectEvent::GaveUp => "gave up",
+        }));
+    client.set_encoding(Encoding::Binary);
+    client.get_size().unwrap();
+    server.disconnect_all();
+
+    let msgs: Vec<_> = (0..8)
+        .flat_map(|y| (0..8).map(move |x| Msg::SetPx(Pos::new(x, y), Rgba::red())))
+        .collect();
+    // Writes may still succeed after the kick, the response of the size is missing for sure
+    client.send_frame(&msgs).unwrap();
+    assert_eq!(client.get_size().unwrap(), Size::new(8, 8));
+    client.send_frame(&msgs).unwrap();
+    client.get_size().unwrap();
+    assert!(server.pixels().iter().all(|&px| px == Rgba::red()));
+    assert_eq!(*events.lock().unwrap(), ["disconnected", "retry", "reconnected"]);
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn async_client() {
+    use barrel::async_client::AsyncClient;
+
+    let server = Server::start(Size::new(16, 16)).unwrap();
+    let mut client = AsyncClient::connect(server.addr()).await.unwrap();
+    assert_eq!(client.get_size().await.unwrap(), Size::new(16, 16));
+    client.set_encoding(Encoding::Binary);
+    let responses = client.send_all(&[
+        Msg::SetPx(Pos::new(3, 4), Rgba::green()),
+        Msg::GetPx(Pos::new(3, 4)),
+        Msg::GetSize,
+    ]).await.unwrap();
+    assert_eq!(responses, [Response::Px(Pos::new(3, 4), Rgba::green()), Response::Size(Size::new(16, 16))]);
+    let help = client.send_recv(Msg::Help).await.unwrap();
+    assert!(matches!(help, Response::Help(help) if help.lines().count() == 6));
+    assert_eq!(client.get_size().await.unwrap(), Size::new(16, 16));
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn read_region() {
+    let (server, mut client) = start(Size::new(32, 32));
+    server.set(Pos::new(10, 20), Rgba::red());
+    let image = client.read_region(Rect::new(Pos::new(8, 16), Size::new(4, 8))).unwrap();
+    assert_eq!(image.dimensions(), (4, 8));
+    assert_eq!(image.get_pixel(2, 4).0, [255, 0, 0, 255]);
+    assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0, 255]);
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn read_region_outside_of_canvas() {
+    let (server, mut client) = start(Size::new(8, 8));
+    server.set(Pos::new(7, 7), Rgba::red());
+    let image = client.read_region(Rect::new(Pos::new(6, 6), Size::new(4, 4))).unwrap();
+    assert_eq!(image.dimensions(), (4, 4));
+    assert_eq!(image.get_pixel(1, 1).0, [255, 0, 0, 255]);
+    assert_eq!(image.get_pixel(2, 2).0, [0, 0, 0, 0]);
+}
+
+#[cfg(feature = "png")]
+#[test]
+fn send_png_image() {
+    use barrel::image_writer::{ImageWriter, LoadOptions};
+
+    let path = std::env::temp_dir().join(format!("barrel-test-{}.png", std::process::id()));
+    let mut image = image::RgbaImage::new(3, 3);
+    image.put_pixel(1, 1, image::Rgba([0, 0, 255, 255]));
+    image.save(&path).unwrap();
+
+    let (server, mut client) = start(Size::new(16, 16));
+    let options = LoadOptions::new(client.encoder()).offset(Pos::new(5, 5));
+    let mut writer = ImageWriter::load(&path, &options).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    client.stream(&mut writer, &StreamOptions::default()).unwrap();
+    client.get_size().unwrap();
+    assert_eq!(server.get(Pos::new(6, 6)), Some(Rgba::blue()));
+    // Transparent pixels are skipped
+    assert_eq!(server.get(Pos::new(5, 5)), Some(Rgba::new(0, 0, 0, None)));
+}